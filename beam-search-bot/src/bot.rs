@@ -0,0 +1,50 @@
+use crate::BeamSearch;
+use lib::*;
+
+pub const BOT_INFO: BotInfo = BotInfo {
+    name: "Beam Search Bot",
+    author: "bpaul",
+    version: "v1",
+    features: &[],
+};
+
+pub struct BeamBot {
+    board: Board,
+    search: BeamSearch<LinearEvaluator>,
+}
+
+impl Bot for BeamBot {
+    type Settings = BeamSearch<LinearEvaluator>;
+
+    fn new(board: Board, _rules: &GameRules, search: Self::Settings) -> Self {
+        BeamBot { board, search }
+    }
+    fn search(&self, search_status: &SearchStatus) {
+        let search = &self.search;
+        let mut generation = search_status.generation();
+        let (mut moves, mut info) = ranked_moves(search, &self.board);
+
+        loop {
+            search_status.current_moves_with_info(&moves, &info);
+            if !search_status.catch_up() {
+                break;
+            }
+            if search_status.generation() != generation {
+                generation = search_status.generation();
+                (moves, info) = ranked_moves(search, &search_status.board());
+            }
+        }
+    }
+}
+
+fn ranked_moves(search: &BeamSearch<LinearEvaluator>, board: &Board) -> (Vec<Move>, MoveInfo) {
+    let (ranked, info) = search.search_with_info(board);
+    let (moves, evaluations) = ranked.into_iter().unzip();
+    (
+        moves,
+        MoveInfo {
+            evaluations,
+            ..info
+        },
+    )
+}
@@ -0,0 +1,232 @@
+//! Beam search over `Board::gen_moves`, and the bot that runs it. Both are kept out of the bot
+//! binary so they can be driven without a frontend, or hosted in process with
+//! `TbpClient::from_bot`.
+
+use lib::*;
+use std::collections::HashSet;
+use std::time::Instant;
+
+mod bot;
+
+pub use crate::bot::*;
+
+#[derive(Clone)]
+pub struct BeamSearch<E: Evaluator> {
+    pub width: usize,
+    pub depth: usize,
+    pub evaluator: E,
+}
+
+#[derive(Clone)]
+struct Node {
+    board: Board,
+    first: Move,
+    // Rewards of every placement on the way here
+    reward: f32,
+    score: f32,
+    // Nothing gets played after a top out, so these stay at TOPPED_OUT
+    topped_out: bool,
+}
+
+impl<E: Evaluator> BeamSearch<E> {
+    pub fn new(width: usize, depth: usize, evaluator: E) -> Self {
+        BeamSearch {
+            width,
+            depth,
+            evaluator,
+        }
+    }
+
+    fn child(&self, node: &Node, mv: Move, first: Move) -> Node {
+        let mut board = node.board.clone();
+        let undo = board.apply(mv);
+        let placement = Placement::new(&board, &undo);
+        let evaluation = self.evaluator.evaluate(&board, &placement);
+        let reward = node.reward + evaluation.reward;
+        let topped_out = mv.locks_out() || board.blocked_out();
+        Node {
+            board,
+            first,
+            reward,
+            score: if topped_out {
+                TOPPED_OUT
+            } else {
+                reward + evaluation.value
+            },
+            topped_out,
+        }
+    }
+
+    // The best `width` nodes, plus the best one for each first move that didn't get any of those,
+    // so every first move still has a score at the end of the beam to be compared on
+    fn keep_best(&self, nodes: &mut Vec<Node>) {
+        nodes.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut firsts = HashSet::new();
+        let mut kept = 0;
+        nodes.retain(|node| {
+            let keep = firsts.insert(node.first) || kept < self.width;
+            kept += 1;
+            keep
+        });
+    }
+
+    // Every move for the current piece (and hold) along with the best score it leads to at the
+    // end of the beam, best first
+    pub fn search(&self, board: &Board) -> Vec<(Move, f32)> {
+        self.search_with_info(board).0
+    }
+
+    // Same as search, along with how many placements got evaluated and how deep it went
+    pub fn search_with_info(&self, board: &Board) -> (Vec<(Move, f32)>, MoveInfo) {
+        let start = Instant::now();
+        let root = Node {
+            board: board.clone(),
+            first: Move {
+                location: Location {
+                    piece: Piece::G,
+                    orientation: Orientation::North,
+                    x: 0,
+                    y: 0,
+                },
+                spin: Spin::None,
+            },
+            reward: 0.0,
+            score: 0.0,
+            topped_out: false,
+        };
+        let mut beam: Vec<Node> = board
+            .gen_moves()
+            .into_iter()
+            .map(|mv| self.child(&root, mv, mv))
+            .collect();
+        let mut nodes = beam.len() as u64;
+        self.keep_best(&mut beam);
+        for _ in 1..self.depth {
+            let mut next = Vec::new();
+            for node in &beam {
+                let moves = if node.topped_out {
+                    Vec::new()
+                } else {
+                    node.board.gen_moves()
+                };
+                if moves.is_empty() {
+                    // Out of queue or topped out, this is as deep as this one goes
+                    next.push(node.clone());
+                }
+                nodes += moves.len() as u64;
+                for mv in moves {
+                    next.push(self.child(node, mv, node.first));
+                }
+            }
+            self.keep_best(&mut next);
+            beam = next;
+        }
+
+        let mut ranked: Vec<(Move, f32)> = Vec::new();
+        for node in &beam {
+            if !ranked.iter().any(|&(mv, _)| mv == node.first) {
+                ranked.push((node.first, node.score));
+            }
+        }
+        let info = MoveInfo {
+            depth: Some(self.depth as u32),
+            ..MoveInfo::with_nodes(nodes, start.elapsed())
+        };
+        (ranked, info)
+    }
+}
+
+// What the bot uses unless it's told otherwise
+impl<E: Evaluator + Default> Default for BeamSearch<E> {
+    fn default() -> Self {
+        BeamSearch::new(100, 3, E::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn takes_the_tetris() {
+        // Four rows with a well on the left, and an I to go in it
+        let mut board = Board::new(vec![Piece::I, Piece::O, Piece::T]);
+        board.fill(0..4, 1..10);
+
+        let search = BeamSearch::new(20, 3, LinearEvaluator::default());
+        let ranked = search.search(&board);
+        let (best, _) = ranked[0];
+        assert!(best.location.cells().iter().all(|&(x, _)| x == 0));
+        assert_eq!(4, board.clone().apply(best).lines_cleared);
+
+        // Every move is in there once
+        let moves = board.gen_moves();
+        assert_eq!(moves.len(), ranked.len());
+        assert!(ranked.iter().all(|(mv, _)| moves.contains(mv)));
+
+        // And it says how much it looked at
+        let (_, info) = search.search_with_info(&board);
+        assert_eq!(Some(3), info.depth);
+        assert!(info.nodes.unwrap() > moves.len() as u64);
+    }
+
+    #[test]
+    fn every_move_is_scored_at_the_end() {
+        // Only one node fits in the beam, but every first move still gets the score of its best
+        // second move rather than its own
+        let board = Board::new(vec![Piece::T, Piece::I, Piece::O]);
+        let search = BeamSearch::new(1, 2, LinearEvaluator::default());
+        let ranked = search.search(&board);
+        assert_eq!(board.gen_moves().len(), ranked.len());
+        for &(mv, score) in &ranked {
+            let root = Node {
+                board: board.clone(),
+                first: mv,
+                reward: 0.0,
+                score: 0.0,
+                topped_out: false,
+            };
+            let after = search.child(&root, mv, mv);
+            let best = after
+                .board
+                .gen_moves()
+                .into_iter()
+                .map(|next| search.child(&after, next, mv).score)
+                .fold(f32::NEG_INFINITY, f32::max);
+            assert_eq!(best, score);
+        }
+    }
+
+    #[test]
+    fn topping_out_is_worst() {
+        // Up to row 17 everywhere but the right column, so an O on top can block the next spawn
+        let mut board = Board::new(vec![Piece::O, Piece::I, Piece::T]);
+        board.fill(0..18, 0..9);
+
+        let search = BeamSearch::new(20, 2, LinearEvaluator::default());
+        let ranked = search.search(&board);
+        let mut topped_out = 0;
+        for &(mv, score) in &ranked {
+            let mut after = board.clone();
+            after.apply(mv);
+            if mv.locks_out() || after.blocked_out() {
+                assert_eq!(TOPPED_OUT, score);
+                topped_out += 1;
+            } else {
+                assert!(score > TOPPED_OUT);
+            }
+        }
+        assert!(topped_out > 0);
+        assert!(ranked[0].1 > TOPPED_OUT);
+    }
+
+    #[test]
+    fn searches_without_hold() {
+        let mut board = Board::new(vec![Piece::S, Piece::I, Piece::O]);
+        board.can_hold = false;
+        let search = BeamSearch::new(10, 2, LinearEvaluator::default());
+        let ranked = search.search(&board);
+        assert_eq!(board.gen_moves().len(), ranked.len());
+        assert!(ranked.iter().all(|(mv, _)| mv.location.piece == Piece::S));
+    }
+}
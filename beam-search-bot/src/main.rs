@@ -0,0 +1,22 @@
+use beam_search_bot::*;
+use bin_utils::parse_arg;
+use lib::*;
+use std::env;
+
+fn main() {
+    let mut search = BeamSearch::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => search.width = parse_arg(args.next(), "width"),
+            "--depth" => search.depth = parse_arg(args.next(), "depth"),
+            "--weights" => {
+                let path = args.next().expect("--weights needs a file");
+                search.evaluator = LinearEvaluator::from_file(&path).expect("couldn't load weights")
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    run_bot::<BeamBot>(BOT_INFO, search);
+}
@@ -0,0 +1,11 @@
+//! Helpers for the command line tools in the workspace. These give up with a panic, which is fine
+//! for a binary reading its arguments but not for anything in `lib`.
+
+use std::str::FromStr;
+
+// The value after a flag like --seed, which has to be there and has to be a number
+pub fn parse_arg<T: FromStr>(value: Option<String>, what: &str) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| panic!("{} should be a number", what))
+}
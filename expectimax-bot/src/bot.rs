@@ -0,0 +1,80 @@
+use crate::Expectimax;
+use lib::*;
+use std::time::{Duration, Instant};
+
+pub const BOT_INFO: BotInfo = BotInfo {
+    name: "Expectimax Bot",
+    author: "bpaul",
+    version: "v1",
+    features: &["randomizer"],
+};
+
+#[derive(Clone)]
+pub struct Settings {
+    pub depth: usize,
+    pub breadth: usize,
+    pub time_limit: Duration,
+    pub evaluator: LinearEvaluator,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            depth: 3,
+            breadth: 8,
+            time_limit: Duration::from_millis(200),
+            evaluator: LinearEvaluator::default(),
+        }
+    }
+}
+
+pub struct ExpectimaxBot {
+    board: Board,
+    settings: Settings,
+}
+
+impl Bot for ExpectimaxBot {
+    type Settings = Settings;
+
+    fn new(board: Board, _rules: &GameRules, settings: Settings) -> Self {
+        ExpectimaxBot { board, settings }
+    }
+    fn search(&self, search_status: &SearchStatus) {
+        let settings = &self.settings;
+        let mut expectimax = Expectimax::new(
+            settings.depth,
+            settings.breadth,
+            settings.time_limit,
+            settings.evaluator.clone(),
+        );
+        let mut generation = search_status.generation();
+        let (mut moves, mut info) =
+            ranked_moves(&mut expectimax, &self.board, &search_status.randomizer());
+
+        loop {
+            search_status.current_moves_with_info(&moves, &info);
+            if !search_status.catch_up() {
+                break;
+            }
+            if search_status.generation() != generation {
+                generation = search_status.generation();
+                let board = search_status.board();
+                (moves, info) = ranked_moves(&mut expectimax, &board, &search_status.randomizer());
+            }
+        }
+    }
+}
+
+fn ranked_moves(
+    expectimax: &mut Expectimax<LinearEvaluator>,
+    board: &Board,
+    randomizer: &Randomizer,
+) -> (Vec<Move>, MoveInfo) {
+    let start = Instant::now();
+    let (moves, evaluations) = expectimax.search(board, randomizer).into_iter().unzip();
+    let info = MoveInfo {
+        evaluations,
+        ..expectimax.search_info(start.elapsed())
+    };
+    (moves, info)
+}
@@ -0,0 +1,180 @@
+//! Expectimax over `Board::gen_moves` with chance nodes for the pieces past the queue, and the
+//! bot that runs it. Both are kept out of the bot binary so they can be driven without a frontend,
+//! or hosted in process with `TbpClient::from_bot`.
+
+use lib::*;
+use std::time::{Duration, Instant};
+
+mod bot;
+
+pub use crate::bot::*;
+
+pub struct Expectimax<E: Evaluator> {
+    // How many placements to look ahead
+    pub depth: usize,
+    // Only this many of the best looking moves at each turn get searched deeper
+    pub breadth: usize,
+    pub time_limit: Duration,
+    evaluator: E,
+    deadline: Instant,
+    out_of_time: bool,
+    pub nodes: u64,
+    // How deep the last search got before it ran out of time
+    pub completed_depth: usize,
+}
+
+impl<E: Evaluator> Expectimax<E> {
+    pub fn new(depth: usize, breadth: usize, time_limit: Duration, evaluator: E) -> Self {
+        Expectimax {
+            depth,
+            breadth,
+            time_limit,
+            evaluator,
+            deadline: Instant::now(),
+            out_of_time: false,
+            nodes: 0,
+            completed_depth: 0,
+        }
+    }
+
+    // Nodes and depth from the last search, which took `elapsed`
+    pub fn search_info(&self, elapsed: Duration) -> MoveInfo {
+        MoveInfo {
+            depth: Some(self.completed_depth as u32),
+            ..MoveInfo::with_nodes(self.nodes, elapsed)
+        }
+    }
+
+    // Every move from this position with its expected score, best first. `randomizer` is the
+    // state after the last piece in the queue. Searches one placement deeper at a time and gives
+    // back the deepest search that finished before the time limit.
+    pub fn search(&mut self, board: &Board, randomizer: &Randomizer) -> Vec<(Move, f32)> {
+        self.deadline = Instant::now() + self.time_limit;
+        self.out_of_time = false;
+        self.nodes = 0;
+        self.completed_depth = 0;
+
+        let mut board = board.clone();
+        let mut ranked = Vec::new();
+        for depth in 1..=self.depth {
+            let mut scores: Vec<(Move, f32)> = board
+                .gen_moves()
+                .into_iter()
+                .map(|mv| (mv, self.placement_score(&mut board, mv, randomizer, depth)))
+                .collect();
+            if self.out_of_time {
+                break;
+            }
+            scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+            ranked = scores;
+            self.completed_depth = depth;
+        }
+        ranked
+    }
+
+    // The reward for making this move plus what we expect to get afterwards
+    fn placement_score(
+        &mut self,
+        board: &mut Board,
+        mv: Move,
+        randomizer: &Randomizer,
+        depth: usize,
+    ) -> f32 {
+        self.nodes += 1;
+        if mv.locks_out() {
+            return TOPPED_OUT;
+        }
+        let undo = board.apply(mv);
+        let placement = Placement::new(board, &undo);
+        let evaluation = self.evaluator.evaluate(board, &placement);
+        let score = if depth <= 1 {
+            evaluation.total()
+        } else {
+            evaluation.reward + self.decision(board, randomizer, depth - 1)
+        };
+        board.undo(undo);
+        score
+    }
+
+    fn decision(&mut self, board: &mut Board, randomizer: &Randomizer, depth: usize) -> f32 {
+        if Instant::now() >= self.deadline {
+            self.out_of_time = true;
+        }
+        if self.out_of_time {
+            return 0.0;
+        }
+        if board.next_pieces().is_empty() {
+            return self.chance(board, randomizer, depth);
+        }
+
+        if board.blocked_out() {
+            return TOPPED_OUT;
+        }
+        let moves = board.gen_moves();
+
+        // Cheap look at every move first so only the promising ones get searched deeper
+        let mut scored: Vec<(Move, f32)> = moves
+            .into_iter()
+            .map(|mv| (mv, self.placement_score(board, mv, randomizer, 1)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(self.breadth);
+
+        scored
+            .into_iter()
+            .map(|(mv, _)| self.placement_score(board, mv, randomizer, depth))
+            .fold(TOPPED_OUT, f32::max)
+    }
+
+    // Average over every piece the randomizer could give us next
+    fn chance(&mut self, board: &mut Board, randomizer: &Randomizer, depth: usize) -> f32 {
+        let mut expected = 0.0;
+        for outcome in randomizer.outcomes() {
+            board.queue.push(outcome.piece);
+            expected += outcome.probability * self.decision(board, &outcome.randomizer, depth);
+            board.queue.pop();
+        }
+        expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn chance_nodes() {
+        use Piece::*;
+        let mut board = Board::new(vec![]);
+        board.fill(0..4, 1..10);
+        let mut expectimax =
+            Expectimax::new(2, 4, Duration::from_secs(60), LinearEvaluator::default());
+        expectimax.deadline = Instant::now() + expectimax.time_limit;
+
+        // With two pieces left in the bag it's the average of just those two
+        let bag = Randomizer::SevenBag {
+            bag_state: vec![I, O],
+        };
+        let mut decisions = Vec::new();
+        for outcome in bag.outcomes() {
+            board.queue.push(outcome.piece);
+            decisions.push(expectimax.decision(&mut board, &outcome.randomizer, 1));
+            board.queue.pop();
+        }
+        let expected = expectimax.chance(&mut board, &bag, 1);
+        assert!((expected - (decisions[0] + decisions[1]) / 2.0).abs() < 1e-4);
+        // The I gets a tetris out of the well so it's better than the O
+        assert!(decisions[0] > decisions[1]);
+
+        // A full bag could be anything, and the O is more likely than the I to be worse
+        let expected_full = expectimax.chance(&mut board, &Randomizer::default(), 1);
+        assert!(expected_full < expected);
+        assert_eq!(board.queue, vec![]);
+
+        // A piece that can't spawn is as bad as it gets
+        board.fill(4..22, 0..10);
+        board.queue.push(T);
+        assert_eq!(TOPPED_OUT, expectimax.decision(&mut board, &bag, 1));
+    }
+}
@@ -0,0 +1,25 @@
+use bin_utils::parse_arg;
+use expectimax_bot::*;
+use lib::*;
+use std::env;
+use std::time::Duration;
+
+fn main() {
+    let mut settings = Settings::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => settings.depth = parse_arg(args.next(), "depth"),
+            "--breadth" => settings.breadth = parse_arg(args.next(), "breadth"),
+            "--time" => settings.time_limit = Duration::from_millis(parse_arg(args.next(), "time")),
+            "--weights" => {
+                let path = args.next().expect("--weights needs a file");
+                settings.evaluator =
+                    LinearEvaluator::from_file(&path).expect("couldn't load weights")
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    run_bot::<ExpectimaxBot>(BOT_INFO, settings);
+}
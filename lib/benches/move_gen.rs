@@ -1,24 +1,28 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use tetris_bot_lib::*;
+use lib::*;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
-    let board = Board {
-        back_to_back: false,
-        board: [[None; 10]; 40],
-        combo: 0,
-        hold: None,
-        queue: vec![
-            Piece::I,
-            Piece::J,
-            Piece::O,
-            Piece::S,
-            Piece::Z,
-            Piece::L,
-            Piece::T,
-        ],
-    };
+    let board = Board::new(vec![
+        Piece::I,
+        Piece::J,
+        Piece::O,
+        Piece::S,
+        Piece::Z,
+        Piece::L,
+        Piece::T,
+    ]);
     c.bench_function("move gen for empty board I piece", |b| {
-        b.iter(|| board.gen_moves(Piece::I))
+        b.iter(|| board.gen_moves_for_piece(Piece::I))
+    });
+    c.bench_function("apply and undo every move on empty board", |b| {
+        let moves = board.gen_moves();
+        let mut board = board.clone();
+        b.iter(|| {
+            for &mv in &moves {
+                let undo = board.apply(mv);
+                board.undo(undo);
+            }
+        })
     });
 }
 
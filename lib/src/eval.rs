@@ -0,0 +1,322 @@
+extern crate serde;
+
+use crate::tetris::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// What happened when a piece got placed, worked out from the board after Board::apply and the
+// undo record it gave back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub mv: Move,
+    pub lines_cleared: u8,
+    pub perfect_clear: bool,
+    // The combo after this placement
+    pub combo: u32,
+    // Whether this clear continued a back to back chain
+    pub back_to_back: bool,
+    pub attack: u32,
+}
+
+impl Placement {
+    pub fn new(board: &Board, undo: &Undo) -> Self {
+        let lines_cleared = undo.lines_cleared;
+        let perfect_clear =
+            lines_cleared > 0 && board.board.iter().flatten().all(|cell| cell.is_none());
+        let difficult = lines_cleared == 4 || (lines_cleared > 0 && undo.mv.spin != Spin::None);
+        let back_to_back = difficult && undo.back_to_back;
+        Placement {
+            mv: undo.mv,
+            lines_cleared,
+            perfect_clear,
+            combo: board.combo,
+            back_to_back,
+            attack: attack(
+                lines_cleared,
+                undo.mv.spin,
+                board.combo,
+                back_to_back,
+                perfect_clear,
+            ),
+        }
+    }
+}
+
+// Guideline style garbage, combo is the combo *after* the clear so the first clear is combo 1
+pub fn attack(lines: u8, spin: Spin, combo: u32, back_to_back: bool, perfect_clear: bool) -> u32 {
+    if lines == 0 {
+        return 0;
+    }
+    let mut attack = match (spin, lines) {
+        (Spin::Full, _) => 2 * lines as u32,
+        (Spin::Mini, _) => lines as u32 - 1,
+        (Spin::None, 4) => 4,
+        (Spin::None, _) => lines as u32 - 1,
+    };
+    if back_to_back {
+        attack += 1;
+    }
+    const COMBO_TABLE: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+    attack += COMBO_TABLE[(combo as usize).min(COMBO_TABLE.len() - 1)];
+    if perfect_clear {
+        attack += 10;
+    }
+    attack
+}
+
+// value is how good the position is, reward is how good the placement that got there was. Search
+// should add up the rewards along a path and add the value of the last board.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Evaluation {
+    pub value: f32,
+    pub reward: f32,
+}
+
+impl Evaluation {
+    pub fn total(&self) -> f32 {
+        self.value + self.reward
+    }
+}
+
+pub trait Evaluator {
+    fn evaluate(&self, board: &Board, placement: &Placement) -> Evaluation;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Weights {
+    // Stack
+    pub height: f32,
+    pub max_height: f32,
+    pub holes: f32,
+    pub covered_cells: f32,
+    pub bumpiness: f32,
+    pub bumpiness_sq: f32,
+    pub well_depth: f32,
+    pub row_transitions: f32,
+    pub column_transitions: f32,
+    // Per fillable tsd/tst slot, slow to work out so it is skipped when this is 0
+    pub tspin_slots: f32,
+
+    // Clears
+    pub clear1: f32,
+    pub clear2: f32,
+    pub clear3: f32,
+    pub clear4: f32,
+    pub mini_tspin: f32,
+    pub tspin1: f32,
+    pub tspin2: f32,
+    pub tspin3: f32,
+    pub perfect_clear: f32,
+    pub combo: f32,
+    pub back_to_back: f32,
+    pub attack: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            height: -0.4,
+            max_height: -0.3,
+            holes: -4.0,
+            covered_cells: -0.5,
+            bumpiness: -0.3,
+            bumpiness_sq: -0.05,
+            well_depth: 0.3,
+            row_transitions: -0.3,
+            column_transitions: -0.5,
+            tspin_slots: 0.0,
+            clear1: -2.0,
+            clear2: -1.5,
+            clear3: -1.0,
+            clear4: 4.0,
+            mini_tspin: -1.0,
+            tspin1: 1.0,
+            tspin2: 5.0,
+            tspin3: 8.0,
+            perfect_clear: 20.0,
+            combo: 0.5,
+            back_to_back: 2.0,
+            attack: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinearEvaluator {
+    pub weights: Weights,
+}
+
+impl LinearEvaluator {
+    pub fn new(weights: Weights) -> Self {
+        LinearEvaluator { weights }
+    }
+
+    // Reads the weights from a .toml file, or a json file for anything else. Missing weights get
+    // their default values.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let weights = if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            toml::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        } else {
+            serde_json::from_str(&text)?
+        };
+        Ok(LinearEvaluator { weights })
+    }
+}
+
+impl Evaluator for LinearEvaluator {
+    fn evaluate(&self, board: &Board, placement: &Placement) -> Evaluation {
+        let w = &self.weights;
+        let features = board.features();
+
+        let height: u32 = features.column_heights.iter().map(|&h| h as u32).sum();
+        let bumpiness_sq: u32 = features
+            .column_heights
+            .windows(2)
+            .map(|pair| (pair[0].abs_diff(pair[1]) as u32).pow(2))
+            .sum();
+        let mut value = w.height * height as f32
+            + w.max_height * features.max_height as f32
+            + w.holes * features.holes as f32
+            + w.covered_cells * features.covered_cells as f32
+            + w.bumpiness * features.bumpiness as f32
+            + w.bumpiness_sq * bumpiness_sq as f32
+            + w.well_depth * features.well_depth.min(4) as f32
+            + w.row_transitions * features.row_transitions as f32
+            + w.column_transitions * features.column_transitions as f32;
+        if w.tspin_slots != 0.0 {
+            let slots = board
+                .tspin_slots()
+                .iter()
+                .filter(|slot| slot.fillable && slot.lines >= 2)
+                .count();
+            value += w.tspin_slots * slots as f32;
+        }
+
+        let mut reward = match (placement.mv.spin, placement.lines_cleared) {
+            (_, 0) => 0.0,
+            (Spin::Mini, _) => w.mini_tspin,
+            (Spin::Full, 1) => w.tspin1,
+            (Spin::Full, 2) => w.tspin2,
+            (Spin::Full, _) => w.tspin3,
+            (Spin::None, 1) => w.clear1,
+            (Spin::None, 2) => w.clear2,
+            (Spin::None, 3) => w.clear3,
+            (Spin::None, _) => w.clear4,
+        };
+        if placement.perfect_clear {
+            reward += w.perfect_clear;
+        }
+        if placement.back_to_back {
+            reward += w.back_to_back;
+        }
+        reward += w.combo * placement.combo as f32 + w.attack * placement.attack as f32;
+
+        Evaluation { value, reward }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::fs;
+
+    #[test]
+    fn attack_table() {
+        assert_eq!(0, attack(0, Spin::Full, 0, true, false));
+        assert_eq!(0, attack(1, Spin::None, 1, false, false));
+        assert_eq!(4, attack(4, Spin::None, 1, false, false));
+        assert_eq!(0, attack(1, Spin::Mini, 1, false, false));
+        assert_eq!(4, attack(2, Spin::Full, 1, false, false));
+        assert_eq!(6, attack(3, Spin::Full, 1, false, false));
+        // Back to back, combo and pc all stack on top
+        assert_eq!(5, attack(2, Spin::Full, 1, true, false));
+        assert_eq!(1 + 2, attack(2, Spin::None, 5, false, false));
+        assert_eq!(1 + 5, attack(2, Spin::None, 50, false, false));
+        assert_eq!(1 + 10, attack(2, Spin::None, 1, false, true));
+    }
+
+    #[test]
+    fn tetris_placement() {
+        let mut board = Board {
+            back_to_back: true,
+            ..Board::new(vec![Piece::I])
+        };
+        board.fill(0..4, 1..10);
+        let mv = *board
+            .gen_moves()
+            .iter()
+            .find(|mv| mv.location.cells().iter().all(|&(x, _)| x == 0))
+            .unwrap();
+        let undo = board.apply(mv);
+        let placement = Placement::new(&board, &undo);
+        assert_eq!(4, placement.lines_cleared);
+        assert!(placement.perfect_clear);
+        assert!(placement.back_to_back);
+        assert_eq!(1, placement.combo);
+        assert_eq!(4 + 1 + 10, placement.attack);
+    }
+
+    fn placement(spin: Spin, lines_cleared: u8) -> Placement {
+        let combo = (lines_cleared > 0) as u32;
+        Placement {
+            mv: Move {
+                location: Location::spawn(Piece::T),
+                spin,
+            },
+            lines_cleared,
+            perfect_clear: false,
+            combo,
+            back_to_back: false,
+            attack: attack(lines_cleared, spin, combo, false, false),
+        }
+    }
+
+    #[test]
+    fn evaluate_with_default_weights() {
+        let evaluator = LinearEvaluator::default();
+        let mut board = Board::new(vec![]);
+        board.fill(0..2, 0..4);
+        let mut holey = board.clone();
+        holey.board[0][1] = None;
+
+        let nothing = placement(Spin::None, 0);
+        let flat = evaluator.evaluate(&board, &nothing);
+        let with_hole = evaluator.evaluate(&holey, &nothing);
+        assert!(with_hole.value < flat.value);
+        assert_eq!(0.0, flat.reward);
+
+        // A tsd is worth more than clearing the same two lines without the spin
+        let tsd = evaluator.evaluate(&board, &placement(Spin::Full, 2));
+        let double = evaluator.evaluate(&board, &placement(Spin::None, 2));
+        assert!(tsd.reward > double.reward);
+        assert_eq!(tsd.value, double.value);
+    }
+
+    #[test]
+    fn weights_from_file() {
+        let dir = std::env::temp_dir();
+        let json = dir.join(format!("weights-{}.json", std::process::id()));
+        let toml = dir.join(format!("weights-{}.toml", std::process::id()));
+        fs::write(&json, r#"{"holes": -7.5, "tspin2": 9}"#).unwrap();
+        fs::write(&toml, "holes = -6.0\nclear4 = 3.0\n").unwrap();
+        let from_json = LinearEvaluator::from_file(&json);
+        let from_toml = LinearEvaluator::from_file(&toml);
+        fs::remove_file(json).unwrap();
+        fs::remove_file(toml).unwrap();
+
+        let weights = from_json.unwrap().weights;
+        assert_eq!((-7.5, 9.0), (weights.holes, weights.tspin2));
+        assert_eq!(Weights::default().clear4, weights.clear4);
+        let weights = from_toml.unwrap().weights;
+        assert_eq!((-6.0, 3.0), (weights.holes, weights.clear4));
+        assert_eq!(Weights::default().tspin2, weights.tspin2);
+    }
+}
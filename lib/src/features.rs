@@ -0,0 +1,191 @@
+use crate::tetris::*;
+
+// Everything evaluators usually want to know about the stack. The per column and per row parts
+// are kept around so update can redo just the bits a placement touched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardFeatures {
+    pub column_heights: [u8; 10],
+    pub max_height: u8,
+    // Empty cells with something above them in the same column
+    pub holes: u32,
+    // Filled cells that are above a hole
+    pub covered_cells: u32,
+    // Sum of the height differences between neighbouring columns
+    pub bumpiness: u32,
+    // How far the deepest column is below both its neighbours (walls count as really tall)
+    pub well_depth: u8,
+    pub well_column: usize,
+    // Filled/empty changes along each row (walls count as filled), only rows below max_height
+    pub row_transitions: u32,
+    // Filled/empty changes going up each column (the floor counts as filled)
+    pub column_transitions: u32,
+
+    column_holes: [u8; 10],
+    column_covered: [u8; 10],
+    column_transitions_by_column: [u8; 10],
+    row_transitions_by_row: [u8; 40],
+}
+
+impl BoardFeatures {
+    fn empty() -> Self {
+        BoardFeatures {
+            column_heights: [0; 10],
+            max_height: 0,
+            holes: 0,
+            covered_cells: 0,
+            bumpiness: 0,
+            well_depth: 0,
+            well_column: 0,
+            row_transitions: 0,
+            column_transitions: 0,
+            column_holes: [0; 10],
+            column_covered: [0; 10],
+            column_transitions_by_column: [0; 10],
+            row_transitions_by_row: [0; 40],
+        }
+    }
+
+    // Bring the features up to date after board.apply(undo.mv). Line clears move every row so
+    // those just get recomputed, otherwise only the rows and columns the piece is in change.
+    // To go back after an undo keep a copy of the old features around, they're Copy.
+    pub fn update(&mut self, board: &Board, undo: &Undo) {
+        if undo.lines_cleared > 0 {
+            *self = board.features();
+            return;
+        }
+        for (x, y) in undo.mv.location.cells() {
+            self.compute_column(board, x as usize);
+            self.compute_row(board, y as usize);
+        }
+        self.compute_totals();
+    }
+
+    fn compute_column(&mut self, board: &Board, x: usize) {
+        let height = (0..40)
+            .rev()
+            .find(|&y| board.board[y][x].is_some())
+            .map_or(0, |y| y + 1);
+
+        let mut holes = 0;
+        let mut covered = 0;
+        let mut transitions = 0;
+        let mut lowest_hole = None;
+        let mut below_filled = true;
+        for y in 0..40 {
+            let filled = board.board[y][x].is_some();
+            if filled != below_filled {
+                transitions += 1;
+            }
+            below_filled = filled;
+            if y >= height {
+                continue;
+            }
+            if !filled {
+                holes += 1;
+                lowest_hole.get_or_insert(y);
+            } else if lowest_hole.is_some() {
+                covered += 1;
+            }
+        }
+
+        self.column_heights[x] = height as u8;
+        self.column_holes[x] = holes;
+        self.column_covered[x] = covered;
+        self.column_transitions_by_column[x] = transitions;
+    }
+
+    fn compute_row(&mut self, board: &Board, y: usize) {
+        let mut transitions = 0;
+        let mut left_filled = true;
+        for cell in board.board[y] {
+            if cell.is_some() != left_filled {
+                transitions += 1;
+            }
+            left_filled = cell.is_some();
+        }
+        if !left_filled {
+            transitions += 1;
+        }
+        self.row_transitions_by_row[y] = transitions;
+    }
+
+    fn compute_totals(&mut self) {
+        let heights = self.column_heights;
+        self.max_height = *heights.iter().max().unwrap();
+        self.holes = self.column_holes.iter().map(|&n| n as u32).sum();
+        self.covered_cells = self.column_covered.iter().map(|&n| n as u32).sum();
+        self.column_transitions = self
+            .column_transitions_by_column
+            .iter()
+            .map(|&n| n as u32)
+            .sum();
+        self.row_transitions = self.row_transitions_by_row[..self.max_height as usize]
+            .iter()
+            .map(|&n| n as u32)
+            .sum();
+        self.bumpiness = heights
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]) as u32)
+            .sum();
+
+        self.well_depth = 0;
+        self.well_column = 0;
+        for x in 0..10 {
+            let left = if x == 0 { u8::MAX } else { heights[x - 1] };
+            let right = if x == 9 { u8::MAX } else { heights[x + 1] };
+            let depth = left.min(right).saturating_sub(heights[x]);
+            if depth > self.well_depth {
+                self.well_depth = depth;
+                self.well_column = x;
+            }
+        }
+    }
+}
+
+impl Board {
+    pub fn features(&self) -> BoardFeatures {
+        let mut features = BoardFeatures::empty();
+        for x in 0..10 {
+            features.compute_column(self, x);
+        }
+        for y in 0..40 {
+            features.compute_row(self, y);
+        }
+        features.compute_totals();
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn incremental_features() {
+        let mut board = Board::new(vec![
+            Piece::I,
+            Piece::O,
+            Piece::L,
+            Piece::J,
+            Piece::S,
+            Piece::Z,
+            Piece::T,
+            Piece::I,
+            Piece::O,
+            Piece::L,
+            Piece::J,
+        ]);
+        let mut features = board.features();
+        assert_eq!(0, features.max_height);
+        assert_eq!(0, features.row_transitions);
+        assert_eq!(10, features.column_transitions);
+
+        // Play a bunch of arbitrary moves, some of which leave holes and clear lines
+        for i in 0..10 {
+            let moves = board.gen_moves();
+            let undo = board.apply(moves[i * 7 % moves.len()]);
+            features.update(&board, &undo);
+            assert_eq!(board.features(), features);
+        }
+    }
+}
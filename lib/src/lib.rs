@@ -1,132 +1,528 @@
 //! This crate acts as an interface that tetris bots can use to interact with a UI, but also to
 //! generate moves and board states etc.
 
-use std::io::{stdin, BufRead, BufReader};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{channel, Sender};
+use std::collections::VecDeque;
+use std::env;
+use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+mod eval;
+mod features;
+mod pc_pruning_table;
+mod perfect_clear;
+mod randomizer;
+mod render;
+mod replay;
+mod sim;
 mod tbp;
+mod tbp_client;
 mod tetris;
+mod tspin;
 
+pub use crate::eval::*;
+pub use crate::features::*;
+pub use crate::pc_pruning_table::*;
+pub use crate::perfect_clear::*;
+pub use crate::randomizer::*;
+pub use crate::replay::*;
+pub use crate::sim::*;
 pub use crate::tbp::*;
+pub use crate::tbp_client::*;
 pub use crate::tetris::*;
+pub use crate::tspin::*;
 
 pub trait Bot {
-    fn new(board: Board) -> Self;
+    // Whatever the bot was started with, like its command line arguments. Each search gets its own
+    // copy, so bots that don't need any can use ()
+    type Settings: Clone + Send + 'static;
+    // Whether the bot can play by the rules the frontend sent, with the reason if it can't
+    fn supports(rules: &GameRules) -> Result<(), String> {
+        rules.check()
+    }
+    fn new(board: Board, rules: &GameRules, settings: Self::Settings) -> Self;
     fn search(&self, search_states: &SearchStatus);
 }
 
+// What the frontend told the search, in the order it was told
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchEvent {
+    // Hold is inferred by the move piece
+    Play(Move),
+    NewPiece(Piece),
+    // The frontend wants moves for the position as it is after everything before this. The next
+    // call to current_moves answers it.
+    Suggest,
+    Stop,
+}
+
+// The longest catch_up waits for the frontend, so a search still notices terminate() even if the
+// stop never comes through
+const IDLE_WAIT: Duration = Duration::from_millis(50);
+
 pub struct SearchStatus {
     terminate: Arc<AtomicBool>,
-    want_moves: Arc<AtomicBool>,
-    new_piece: Arc<Mutex<Option<Piece>>>,
-    suggestion_sender: Sender<Vec<Move>>,
-    // Receive a move when the Play tbp command is sent
-    play_move: Arc<Mutex<Option<Move>>>,
+    // The number of the last Suggest the bot picked up, until current_moves answers it. Zero when
+    // there's nothing to answer.
+    want_moves: AtomicU64,
+    // Everything for this search that the bot hasn't picked up yet. Suggests come with their
+    // number so run_bot can tell the answers apart, it's zero for everything else.
+    events: Mutex<Receiver<(SearchEvent, u64)>>,
+    suggestion_sender: Sender<Input>,
+    // The position after every Play and NewPiece the bot has picked up, and how many of those
+    // there have been
+    board: Mutex<Board>,
+    generation: AtomicU64,
+    // The randomizer after the last piece the bot has picked up
+    randomizer: Mutex<Randomizer>,
+    started: Instant,
 }
 
 impl SearchStatus {
+    fn new(
+        terminate: Arc<AtomicBool>,
+        events: Receiver<(SearchEvent, u64)>,
+        suggestion_sender: Sender<Input>,
+        board: Board,
+        randomizer: Randomizer,
+    ) -> Self {
+        SearchStatus {
+            terminate,
+            want_moves: AtomicU64::new(0),
+            events: Mutex::new(events),
+            suggestion_sender,
+            board: Mutex::new(board),
+            generation: AtomicU64::new(0),
+            randomizer: Mutex::new(randomizer),
+            started: Instant::now(),
+        }
+    }
     pub fn terminate(&self) -> bool {
         !self.terminate.load(Ordering::Acquire)
     }
-    pub fn current_moves(&self, moves: &Vec<Move>) {
-        if self.want_moves.load(Ordering::Acquire) {
-            self.suggestion_sender.send(moves.to_vec()).unwrap();
-            self.want_moves.store(false, Ordering::Release);
+    pub fn current_moves(&self, moves: &[Move]) {
+        self.send_moves(moves, None);
+    }
+    // Same as current_moves, with some stats about the search for the frontend. The info only
+    // gets cloned if there's a suggest waiting for it.
+    pub fn current_moves_with_info(&self, moves: &[Move], info: &MoveInfo) {
+        self.send_moves(moves, Some(info));
+    }
+    fn send_moves(&self, moves: &[Move], info: Option<&MoveInfo>) {
+        // A search that got stopped might not have noticed yet, and the suggestion is for the
+        // search that replaced it
+        if self.terminate() {
+            return;
+        }
+        // Nothing to play isn't an answer, like between a play and the piece after it when the
+        // queue ran out. The suggest waits for the next moves instead.
+        if moves.is_empty() {
+            return;
         }
+        // Swap so each suggest only gets answered once
+        let id = self.want_moves.swap(0, Ordering::AcqRel);
+        if id != 0 {
+            // The loop only goes away once the bot is quitting, so nobody's listening anyway
+            let _ = self
+                .suggestion_sender
+                .send(Input::Moves(id, moves.to_vec(), info.cloned()));
+        }
+    }
+    // How long since the start message for this search
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+    // The next thing from the frontend, or None if there's nothing new. Every Play, NewPiece,
+    // Suggest and Stop sent after the start message comes out of here once, in order. Plays and
+    // new pieces have already been applied to board() by the time they come out, and plays that
+    // couldn't be made never get this far.
+    pub fn next_event(&self) -> Option<SearchEvent> {
+        let (event, suggest) = self.events.lock().unwrap().try_recv().ok()?;
+        Some(self.pick_up(event, suggest))
     }
-    pub fn new_move(&self) -> Option<Move> {
-        let mut move_ptr = self.play_move.lock().unwrap();
-        if let Some(mv) = *move_ptr {
-            *move_ptr = None;
-            return Some(mv);
+    // Same as next_event, but waits up to `timeout` for something to come in so a bot with nothing
+    // to do doesn't have to spin
+    pub fn wait_event(&self, timeout: Duration) -> Option<SearchEvent> {
+        let (event, suggest) = self.events.lock().unwrap().recv_timeout(timeout).ok()?;
+        Some(self.pick_up(event, suggest))
+    }
+    // Applies an event to the position before the bot gets it
+    fn pick_up(&self, event: SearchEvent, suggest: u64) -> SearchEvent {
+        match event {
+            // serve only sends plays it's already checked against the same position
+            SearchEvent::Play(mv) => self.board.lock().unwrap().make_move_in_place(mv),
+            SearchEvent::NewPiece(piece) => {
+                self.board.lock().unwrap().queue.push(piece);
+                if !self.randomizer.lock().unwrap().take(piece) {
+                    eprintln!(
+                        "{:?} couldn't have come out of the randomizer, starting a new bag",
+                        piece
+                    );
+                }
+            }
+            SearchEvent::Suggest => self.want_moves.store(suggest, Ordering::Release),
+            SearchEvent::Stop => {}
         }
-        None
+        if matches!(event, SearchEvent::Play(_) | SearchEvent::NewPiece(_)) {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+        event
     }
-    pub fn new_piece(&self) -> Option<Piece> {
-        let mut piece_ptr = self.new_piece.lock().unwrap();
-        if let Some(piece) = *piece_ptr {
-            *piece_ptr = None;
-            return Some(piece);
+    // For bots that only care about the position: picks up everything up to the next suggest,
+    // so it gets answered for the position it was asked about. If nothing's come in it waits a
+    // little for the frontend first, since these bots have nothing else to do until it does.
+    // False once the search should stop.
+    pub fn catch_up(&self) -> bool {
+        let mut event = self.wait_event(IDLE_WAIT);
+        while let Some(picked_up) = event {
+            match picked_up {
+                SearchEvent::Suggest => break,
+                SearchEvent::Stop => return false,
+                SearchEvent::Play(_) | SearchEvent::NewPiece(_) => {}
+            }
+            event = self.next_event();
         }
-        None
+        !self.terminate()
+    }
+    // The current position, as of the last event the bot picked up
+    pub fn board(&self) -> Board {
+        self.board.lock().unwrap().clone()
+    }
+    // Goes up by one for every Play and NewPiece picked up, so a bot can tell when board() has
+    // changed without comparing boards
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+    // Where the randomizer is after the queue the bot knows about. This is from the start message
+    // if the frontend sent it, otherwise it's guessed from the rules and the queue.
+    pub fn randomizer(&self) -> Randomizer {
+        self.randomizer.lock().unwrap().clone()
     }
 }
 
-pub fn run_bot<B: Bot>(info: BotInfo) {
-    BotMessage::Info(info).send_message();
+// Everything run_bot waits on comes through one channel, so it can keep reading stdin while a
+// suggest is waiting for the search
+enum Input {
+    Line(String),
+    // The search's answer to the suggest with this number
+    Moves(u64, Vec<Move>, Option<MoveInfo>),
+    // The frontend's end closed or couldn't be read
+    Closed,
+}
 
-    let calculating = Arc::new(AtomicBool::new(false));
-    let want_moves = Arc::new(AtomicBool::new(false));
-    let play_move: Arc<Mutex<Option<Move>>> = Arc::new(Mutex::new(None));
-    let new_piece: Arc<Mutex<Option<Piece>>> = Arc::new(Mutex::new(None));
+// How long a suggest waits for the search before getting whatever we already have. Set
+// TBP_SUGGEST_TIMEOUT in milliseconds to change it.
+const SUGGEST_TIMEOUT: Duration = Duration::from_secs(1);
 
-    let mut reader = BufReader::new(stdin()).lines();
-    let (move_sender, move_receiver) = channel();
+// The answer for a suggest the search didn't get to in time
+fn fallback_suggestion(last: &Option<(Vec<Move>, Option<MoveInfo>)>) -> BotMessage {
+    match last {
+        Some((moves, move_info)) => BotMessage::Suggestion {
+            moves: moves.clone(),
+            move_info: move_info.clone(),
+        },
+        None => BotMessage::Error {
+            reason: "no moves ready yet, the bot might not be calculating".to_string(),
+        },
+    }
+}
 
-    while let Some(Ok(line)) = reader.next() {
-        let msg = get_frontend_message(line).unwrap();
-        match msg {
-            FrontendMessage::Rules => {
-                // Maybe one day the rules of a game will be parsed here, but right now this
-                // message is empty
-                BotMessage::Ready.send_message();
+pub fn run_bot<B: Bot>(info: BotInfo, settings: B::Settings) {
+    run_bot_with::<B, _, _>(info, stdin(), stdout(), settings);
+}
+
+// Same as run_bot, but talking tbp over `reader` and `writer` instead of stdin and stdout. This is
+// how TbpClient::from_bot runs a bot on a thread.
+pub fn run_bot_with<B: Bot, R: Read + Send + 'static, W: Write>(
+    info: BotInfo,
+    reader: R,
+    mut writer: W,
+    settings: B::Settings,
+) {
+    // Nothing's listening if this fails, and then the reader closes too and that ends things
+    let mut send = move |message: BotMessage| {
+        let _ = message.write_to(&mut writer);
+    };
+    send(BotMessage::Info(info));
+
+    // Set TBP_REPLAY to a file to get a replay of everything the frontend tells the bot
+    let replay = env::var_os("TBP_REPLAY").and_then(|path| {
+        ReplayWriter::create(&path)
+            .map_err(|e| eprintln!("Couldn't create the replay file: {}", e))
+            .ok()
+    });
+
+    let suggest_timeout = env::var("TBP_SUGGEST_TIMEOUT")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map_or(SUGGEST_TIMEOUT, Duration::from_millis);
+
+    // Set TBP_LOG_IGNORED to hear about fields in messages that the bot doesn't know about. It
+    // means reading every message twice, so it's off unless you ask.
+    let log_ignored = env::var_os("TBP_LOG_IGNORED").is_some();
+
+    let (input_sender, inputs) = channel();
+    thread::spawn({
+        let input_sender = input_sender.clone();
+        move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if input_sender.send(Input::Line(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = input_sender.send(Input::Closed);
+        }
+    });
+
+    serve::<B, _>(
+        inputs,
+        input_sender,
+        settings,
+        suggest_timeout,
+        log_ignored,
+        replay,
+        send,
+    );
+}
+
+// Everything run_bot does once stdin and stdout are hooked up, so tests can play frontend
+fn serve<B: Bot, W: Write>(
+    inputs: Receiver<Input>,
+    input_sender: Sender<Input>,
+    settings: B::Settings,
+    suggest_timeout: Duration,
+    log_ignored: bool,
+    mut replay: Option<ReplayWriter<W>>,
+    mut send: impl FnMut(BotMessage),
+) {
+    // Every search gets its own flag so one that was stopped can't be brought back by the next
+    // start before it notices
+    let mut calculating = Arc::new(AtomicBool::new(false));
+    // Where events for the current search go, None once it's been stopped
+    let mut search: Option<Sender<(SearchEvent, u64)>> = None;
+    let mut rules = GameRules::default();
+
+    // Suggests are numbered so an answer that shows up after its suggest gave up waiting can't be
+    // taken for the answer to a later one
+    let mut next_suggest = 1;
+    // The suggests the search hasn't answered yet, oldest first, with when each gives up waiting
+    let mut waiting: VecDeque<(u64, Instant)> = VecDeque::new();
+    // The first suggest that was for the current position, anything older is out of date
+    let mut position_from = next_suggest;
+    // The last moves the search sent, as long as they're still for the current position
+    let mut last_suggestion = None;
+    // The position after everything the frontend sent, so a play can be checked before it goes to
+    // the search or the replay. None when nothing's been started.
+    let mut position: Option<Board> = None;
+
+    // Only losing stdin ends the loop, a bad line gets complained about and skipped
+    loop {
+        // We hold a sender ourselves so the channel can't disconnect
+        let input = match waiting.front() {
+            Some(&(_, deadline)) => {
+                match inputs.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(input) => input,
+                    Err(_) => {
+                        while let Some(&(_, deadline)) = waiting.front() {
+                            if deadline > Instant::now() {
+                                break;
+                            }
+                            waiting.pop_front();
+                            send(fallback_suggestion(&last_suggestion));
+                        }
+                        continue;
+                    }
+                }
+            }
+            None => inputs.recv().unwrap(),
+        };
+        let line = match input {
+            Input::Line(line) => line,
+            Input::Moves(id, moves, move_info) => {
+                // Suggests older than this one were skipped by the search and aren't going to get
+                // an answer of their own. One that already got the fallback doesn't get another.
+                if let Some(index) = waiting.iter().position(|&(waiting, _)| waiting == id) {
+                    for _ in 0..index {
+                        waiting.pop_front();
+                        send(fallback_suggestion(&last_suggestion));
+                    }
+                    waiting.pop_front();
+                    send(BotMessage::Suggestion {
+                        moves: moves.clone(),
+                        move_info: move_info.clone(),
+                    });
+                }
+                if id >= position_from {
+                    last_suggestion = Some((moves, move_info));
+                }
+                continue;
+            }
+            Input::Closed => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let msg = match parse_frontend_message(&line) {
+            Ok(msg) => {
+                if log_ignored {
+                    let ignored = ignored_fields(&line, &msg);
+                    if !ignored.is_empty() {
+                        eprintln!(
+                            "Ignoring {} in a message, which this bot doesn't know about",
+                            ignored.join(", ")
+                        );
+                    }
+                }
+                msg
+            }
+            Err(BadMessage::Unknown(kind)) => {
+                eprintln!(
+                    "Ignoring a {} message, which this bot doesn't know about",
+                    kind
+                );
+                continue;
             }
+            Err(BadMessage::Invalid(e)) => {
+                eprintln!("Couldn't read the message {}: {}", line, e);
+                send(BotMessage::Error {
+                    reason: format!("couldn't read the message: {}", e),
+                });
+                continue;
+            }
+        };
+        // Starts and plays only go in the replay once they've been taken
+        let event = ReplayEvent::from_frontend(&msg);
+        if !matches!(
+            msg,
+            FrontendMessage::Start(_) | FrontendMessage::Play { .. }
+        ) {
+            record(&mut replay, event.clone());
+        }
+        match msg {
+            FrontendMessage::Rules(game_rules) => match B::supports(&game_rules) {
+                Ok(()) => {
+                    rules = game_rules;
+                    send(BotMessage::Ready);
+                }
+                Err(reason) => {
+                    // The frontend isn't going to send anything we can play, so that's it. The
+                    // reason is only for people reading the log, frontends get the one tbp has.
+                    eprintln!("Can't play by these rules: {}", reason);
+                    send(BotMessage::Error {
+                        reason: UNSUPPORTED_RULES.to_string(),
+                    });
+                    break;
+                }
+            },
             FrontendMessage::Quit => {
                 break;
             }
             FrontendMessage::Stop => {
                 // Tell the bot to stop calculating
                 calculating.store(false, Ordering::Release);
+                if let Some(search) = search.take() {
+                    let _ = search.send((SearchEvent::Stop, 0));
+                }
+                position = None;
+                position_from = next_suggest;
+                last_suggestion = None;
             }
             FrontendMessage::Suggest => {
                 // Tell the bot to suggest some moves in order of preference.
-                // Only valid to recieve if bot is calculating
-                // Bot should send a suggestion message
-                want_moves.store(true, Ordering::Release);
-                BotMessage::Suggestion {
-                    moves: move_receiver.recv().unwrap(),
+                // Only valid to recieve if bot is calculating, otherwise nothing is going to
+                // answer so it gets the fallback straight away
+                // Bot should send a suggestion message, which gets passed on when it comes in
+                let id = next_suggest;
+                next_suggest += 1;
+                let sent = search
+                    .as_ref()
+                    .is_some_and(|search| search.send((SearchEvent::Suggest, id)).is_ok());
+                if sent {
+                    waiting.push_back((id, Instant::now() + suggest_timeout));
+                } else if waiting.is_empty() {
+                    send(fallback_suggestion(&last_suggestion));
+                } else {
+                    // Answers have to go out in order, so this one waits behind the others and
+                    // gets the fallback when they're done
+                    waiting.push_back((id, Instant::now()));
                 }
-                .send_message();
             }
             FrontendMessage::Play { mv } => {
                 // Tell the bot to update the game state by applying the move specified and begin
                 // calculating from the new position
                 // Only valid to recieve if bot is calculating
-                // Hold is inferred by the move piece
-                let mut move_ptr = play_move.lock().unwrap();
-                *move_ptr = Some(mv);
+                let Some(board) = &mut position else {
+                    continue;
+                };
+                let Some(mv) = board.legal_move(mv) else {
+                    eprintln!(
+                        "Got a move that can't be played here, skipping it: {:?}",
+                        mv
+                    );
+                    send(BotMessage::Error {
+                        reason: format!("can't play {:?} in the current position", mv.location),
+                    });
+                    continue;
+                };
+                board.make_move_in_place(mv);
+                record(&mut replay, Some(ReplayEvent::Move { mv }));
+                if let Some(search) = &search {
+                    let _ = search.send((SearchEvent::Play(mv), 0));
+                }
+                position_from = next_suggest;
+                last_suggestion = None;
             }
             FrontendMessage::NewPiece { piece } => {
                 // Tell the bot that a new piece is added to the queue
-                let mut piece_ptr = new_piece.lock().unwrap();
-                *piece_ptr = Some(piece);
+                if let Some(board) = &mut position {
+                    board.queue.push(piece);
+                }
+                if let Some(search) = &search {
+                    let _ = search.send((SearchEvent::NewPiece(piece), 0));
+                }
             }
             FrontendMessage::Start(tbp_board) => {
                 // Tell the bot to begin calculating the given position
                 if calculating.load(Ordering::Acquire) {
-                    println!("Bot was already calculating");
+                    // Stdout is only for tbp messages
+                    eprintln!("Got a start while the bot was already calculating, ignoring it");
+                    send(BotMessage::Error {
+                        reason: "already calculating, send stop first".to_string(),
+                    });
                 } else {
                     // Create the board based on input
+                    let randomizer = match tbp_board.randomizer.clone() {
+                        Some(randomizer) => randomizer.normalized(),
+                        None => rules.infer_randomizer(&tbp_board.queue),
+                    };
                     let board = Board::from_tbp(tbp_board);
-                    calculating.store(true, Ordering::Release);
+                    record(&mut replay, event);
+                    position = Some(board.clone());
+                    calculating = Arc::new(AtomicBool::new(true));
+                    let (events, receiver) = channel();
+                    search = Some(events);
+                    position_from = next_suggest;
+                    last_suggestion = None;
                     thread::spawn({
                         let terminate = calculating.clone();
-                        let want_moves = want_moves.clone();
-                        let new_piece = new_piece.clone();
-                        let move_sender = move_sender.clone();
-                        let play_move = play_move.clone();
+                        let input_sender = input_sender.clone();
+                        let rules = rules.clone();
+                        let settings = settings.clone();
                         move || {
-                            let search_status = SearchStatus {
+                            let search_status = SearchStatus::new(
                                 terminate,
-                                want_moves,
-                                new_piece,
-                                suggestion_sender: move_sender,
-                                play_move,
-                            };
-                            let bot = B::new(board);
+                                receiver,
+                                input_sender,
+                                board.clone(),
+                                randomizer,
+                            );
+                            let bot = B::new(board, &rules, settings);
                             bot.search(&search_status);
                         }
                     });
@@ -135,3 +531,201 @@ pub fn run_bot<B: Bot>(info: BotInfo) {
         }
     }
 }
+
+// Gives up on the replay if writing to it ever fails
+fn record<W: Write>(replay: &mut Option<ReplayWriter<W>>, event: Option<ReplayEvent>) {
+    if let (Some(writer), Some(event)) = (replay.as_mut(), event) {
+        if let Err(e) = writer.write(event) {
+            eprintln!("Couldn't write to the replay, giving up on it: {}", e);
+            *replay = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    // Sits on its answer to the first suggest until the move after it comes in
+    struct LateBot;
+
+    fn first_move(board: &Board) -> Vec<Move> {
+        let piece = board.next_pieces()[0];
+        let moves = board.gen_moves();
+        moves
+            .into_iter()
+            .filter(|mv| mv.location.piece == piece)
+            .take(1)
+            .collect()
+    }
+
+    impl Bot for LateBot {
+        type Settings = ();
+
+        fn new(_: Board, _: &GameRules, _: ()) -> Self {
+            LateBot
+        }
+
+        fn search(&self, status: &SearchStatus) {
+            let mut late = None;
+            let mut suggests = 0;
+            while !status.terminate() {
+                let Some(event) = status.next_event() else {
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                };
+                match event {
+                    SearchEvent::Suggest => {
+                        suggests += 1;
+                        if suggests == 1 {
+                            late = Some(first_move(&status.board()));
+                        } else {
+                            status.current_moves(&first_move(&status.board()));
+                        }
+                    }
+                    SearchEvent::Play(_) => {
+                        if let Some(moves) = late.take() {
+                            status.current_moves(&moves);
+                        }
+                    }
+                    SearchEvent::NewPiece(_) => {}
+                    SearchEvent::Stop => return,
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn late_answer_after_a_play() {
+        let (input_sender, inputs) = channel();
+        let (output_sender, outputs) = channel();
+        let runner = thread::spawn({
+            let input_sender = input_sender.clone();
+            move || {
+                serve::<LateBot, Vec<u8>>(
+                    inputs,
+                    input_sender,
+                    (),
+                    Duration::from_millis(200),
+                    false,
+                    None,
+                    |message| output_sender.send(message).unwrap(),
+                )
+            }
+        });
+        let send = |message: FrontendMessage| {
+            let line = serde_json::to_string(&message).unwrap();
+            input_sender.send(Input::Line(line)).unwrap();
+        };
+
+        let board = Board::new(vec![Piece::T, Piece::I, Piece::O]);
+        send(FrontendMessage::Rules(GameRules::default()));
+        assert!(matches!(outputs.recv().unwrap(), BotMessage::Ready));
+        send(FrontendMessage::Start(board.to_tbp()));
+        send(FrontendMessage::Suggest);
+        // Nothing to fall back on yet
+        assert!(matches!(outputs.recv().unwrap(), BotMessage::Error { .. }));
+
+        // The answer to the first suggest turns up now, after the play, and has to be dropped
+        send(FrontendMessage::Play {
+            mv: first_move(&board)[0],
+        });
+        send(FrontendMessage::Suggest);
+        match outputs.recv().unwrap() {
+            BotMessage::Suggestion { moves, .. } => assert_eq!(Piece::I, moves[0].location.piece),
+            _ => panic!("expected a suggestion"),
+        }
+
+        send(FrontendMessage::Stop);
+        send(FrontendMessage::Quit);
+        runner.join().unwrap();
+        assert!(outputs.try_recv().is_err());
+    }
+
+    #[test]
+    fn unsupported_rules() {
+        let (input_sender, inputs) = channel();
+        let (output_sender, outputs) = channel();
+        let rules = GameRules {
+            randomizer: Some("classic".to_string()),
+            ..GameRules::default()
+        };
+        let line = serde_json::to_string(&FrontendMessage::Rules(rules)).unwrap();
+        input_sender.send(Input::Line(line)).unwrap();
+        serve::<LateBot, Vec<u8>>(
+            inputs,
+            input_sender,
+            (),
+            Duration::from_millis(200),
+            false,
+            None,
+            |message| output_sender.send(message).unwrap(),
+        );
+        match outputs.try_recv() {
+            Ok(BotMessage::Error { reason }) => assert_eq!(UNSUPPORTED_RULES, reason),
+            _ => panic!("expected an error"),
+        }
+        assert!(outputs.try_recv().is_err());
+    }
+
+    #[test]
+    fn only_taken_plays_are_recorded() {
+        let path = env::temp_dir().join(format!("tetris-serve-{}.jsonl", std::process::id()));
+        let (input_sender, inputs) = channel();
+        let (output_sender, outputs) = channel();
+        let runner = thread::spawn({
+            let input_sender = input_sender.clone();
+            let replay = ReplayWriter::create(&path).unwrap();
+            move || {
+                serve::<LateBot, _>(
+                    inputs,
+                    input_sender,
+                    (),
+                    Duration::from_millis(200),
+                    true,
+                    Some(replay),
+                    |message| output_sender.send(message).unwrap(),
+                )
+            }
+        });
+        let send = |message: FrontendMessage| {
+            let line = serde_json::to_string(&message).unwrap();
+            input_sender.send(Input::Line(line)).unwrap();
+        };
+
+        let board = Board::new(vec![Piece::T, Piece::I]);
+        let t = first_move(&board)[0];
+        send(FrontendMessage::Play { mv: t });
+        send(FrontendMessage::Start(board.to_tbp()));
+        send(FrontendMessage::Start(board.to_tbp()));
+        assert!(matches!(outputs.recv().unwrap(), BotMessage::Error { .. }));
+        send(FrontendMessage::Play {
+            mv: Move {
+                location: Location {
+                    x: 30,
+                    ..t.location
+                },
+                spin: Spin::None,
+            },
+        });
+        assert!(matches!(outputs.recv().unwrap(), BotMessage::Error { .. }));
+        send(FrontendMessage::Play {
+            mv: Move {
+                spin: Spin::Full,
+                ..t
+            },
+        });
+        send(FrontendMessage::Quit);
+        runner.join().unwrap();
+
+        // The play before the start, the second start and the bad play never happened
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let events: Vec<_> = replay.entries.iter().map(|entry| &entry.event).collect();
+        assert_eq!(3, events.len());
+        assert!(matches!(events[0], ReplayEvent::Start { .. }));
+        assert!(matches!(events[1], ReplayEvent::Move { mv } if *mv == t));
+        assert!(matches!(events[2], ReplayEvent::End { .. }));
+        assert_eq!(board.make_move(t), replay.boards()[1]);
+    }
+}
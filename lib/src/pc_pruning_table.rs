@@ -0,0 +1,315 @@
+use crate::perfect_clear::*;
+use crate::randomizer::*;
+use crate::tetris::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PCPT";
+const VERSION: u8 = 1;
+
+// Every field that comes up on the way from an empty board (within `height` rows) and can still be
+// perfect cleared with *some* pieces. Each field is stored as a key with the remaining height in
+// the top bits and the field in the bottom 60, see PcPruningTable::key. There are no solutions in
+// here, it isn't keyed by pieces, so all it can do is rule fields out for a pc search. Storing the
+// moves for every piece on every field would be many times the size, and this is already slow to
+// generate for 4 lines.
+//
+// The table only covers boards with nothing above its height and a field that's in it. Fields
+// that never come up from an empty board aren't in it, and neither are ones that can't be cleared,
+// so it can't tell those two apart and doesn't answer for either.
+pub struct PcPruningTable {
+    pub height: usize,
+    keys: Vec<u64>,
+}
+
+impl PcPruningTable {
+    #[inline]
+    fn key(height: usize, field: u64) -> u64 {
+        ((height as u64) << 60) | field
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, height: usize, field: u64) -> bool {
+        self.keys.binary_search(&Self::key(height, field)).is_ok()
+    }
+
+    // Walk every field reachable from an empty board with any pieces and keep the ones that can
+    // still reach a pc. 4 lines takes a long time, upwards of 15 minutes and several hundred MB,
+    // hence the generator binary. An empty board
+    // only has a pc in an even number of lines, so that's all `height` can be.
+    pub fn generate(height: usize) -> Self {
+        assert!(height <= MAX_PC_HEIGHT && height.is_multiple_of(2));
+        let mut seen = FxHashMap::default();
+        can_clear(&mut seen, height, 0);
+        let mut keys: Vec<u64> = seen
+            .into_iter()
+            .filter(|&(_, clearable)| clearable)
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort_unstable();
+        PcPruningTable { height, keys }
+    }
+
+    // Whether find_perfect_clear can answer for this board. For the ones it can't,
+    // Board::find_perfect_clear still can.
+    pub fn covers(&self, board: &Board) -> bool {
+        // Lower heights are in the table too, but only as what's left after clearing lines from
+        // this one, and a pc in fewer lines is still found from here
+        board.board[self.height..]
+            .iter()
+            .flatten()
+            .all(|cell| cell.is_none())
+            && self.contains(self.height, board.field_bits(self.height))
+    }
+
+    // Is there a pc on this board with its queue and hold, within the table's height? None when
+    // the table doesn't cover the board, see covers. The moves come from a depth first search over
+    // gen_moves that the table prunes, so this is a faster solver rather than an answer straight
+    // out of the table.
+    pub fn find_perfect_clear(&self, board: &Board) -> Option<Option<Vec<Move>>> {
+        if !self.covers(board) {
+            return None;
+        }
+        let mut board = board.clone();
+        let mut moves = Vec::new();
+        let mut dead = FxHashSet::default();
+        Some(
+            self.solve(&mut board, self.height, &mut moves, &mut dead)
+                .then_some(moves),
+        )
+    }
+
+    pub fn has_perfect_clear(&self, board: &Board) -> Option<bool> {
+        self.find_perfect_clear(board).map(|moves| moves.is_some())
+    }
+
+    // Everything after a field in the table stays in the table, so pruning with it never misses
+    // a pc. `dead` has the positions this queue already failed to clear from.
+    fn solve(
+        &self,
+        board: &mut Board,
+        height: usize,
+        moves: &mut Vec<Move>,
+        dead: &mut FxHashSet<PcKey>,
+    ) -> bool {
+        let key = (
+            board.field_bits(height),
+            height,
+            board.hold,
+            board.queue_cursor,
+        );
+        if dead.contains(&key) {
+            return false;
+        }
+        for mv in distinct_placements(board.gen_moves()) {
+            if !fits_below(&mv, height) {
+                continue;
+            }
+            let undo = board.apply(mv);
+            moves.push(mv);
+            let height = height - undo.lines_cleared as usize;
+            let field = board.field_bits(height);
+            if field == 0
+                || (self.contains(height, field) && self.solve(board, height, moves, dead))
+            {
+                return true;
+            }
+            moves.pop();
+            board.undo(undo);
+        }
+        dead.insert(key);
+        false
+    }
+
+    // The file is a small header and then the sorted keys as varint encoded gaps, most of the
+    // gaps are tiny so this is a lot smaller than 8 bytes a field
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.height as u8])?;
+        writer.write_all(&(self.keys.len() as u64).to_le_bytes())?;
+        let mut previous = 0;
+        for &key in &self.keys {
+            let mut gap = key - previous;
+            previous = key;
+            loop {
+                let byte = (gap & 0x7f) as u8;
+                gap >>= 7;
+                if gap == 0 {
+                    writer.write_all(&[byte])?;
+                    break;
+                }
+                writer.write_all(&[byte | 0x80])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut header = [0; 14];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a pc pruning table file",
+            ));
+        }
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+        let height = header[5] as usize;
+        if height > MAX_PC_HEIGHT {
+            return Err(invalid("the pruning table is taller than a pc can be"));
+        }
+        let len = u64::from_le_bytes(header[6..].try_into().unwrap()) as usize;
+
+        // The length comes from the file, so don't trust it with more than a few MB up front
+        let mut keys = Vec::with_capacity(len.min(1 << 20));
+        let mut previous: u64 = 0;
+        let mut byte = [0];
+        for _ in 0..len {
+            let mut gap = 0;
+            let mut shift = 0;
+            loop {
+                reader.read_exact(&mut byte)?;
+                if shift >= 64 {
+                    return Err(invalid("a key in the pruning table is too long"));
+                }
+                gap |= ((byte[0] & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+            previous = previous
+                .checked_add(gap)
+                .ok_or_else(|| invalid("a key in the pruning table is too big"))?;
+            keys.push(previous);
+        }
+        Ok(PcPruningTable { height, keys })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn can_clear(seen: &mut FxHashMap<u64, bool>, height: usize, field: u64) -> bool {
+    let key = PcPruningTable::key(height, field);
+    if let Some(&clearable) = seen.get(&key) {
+        return clearable;
+    }
+
+    let mut board = Board::new(Vec::new());
+    for y in 0..height {
+        for x in 0..10 {
+            if field & (1 << (y * 10 + x)) != 0 {
+                board.board[y][x] = Some('G');
+            }
+        }
+    }
+    if !pc_possible(&board, field, height) {
+        seen.insert(key, false);
+        return false;
+    }
+
+    // Don't stop at the first pc, every field on the way has to end up in the table
+    let mut clearable = false;
+    for piece in PIECES {
+        board.queue.push(piece);
+        for mv in distinct_placements(board.gen_moves_for_piece(piece)) {
+            if !fits_below(&mv, height) {
+                continue;
+            }
+            let undo = board.apply(mv);
+            let height = height - undo.lines_cleared as usize;
+            let field = board.field_bits(height);
+            if field == 0 || can_clear(seen, height, field) {
+                clearable = true;
+            }
+            board.undo(undo);
+        }
+        board.queue.pop();
+    }
+
+    seen.insert(key, clearable);
+    clearable
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::io;
+
+    #[test]
+    fn table_matches_solver() {
+        let table = PcPruningTable::generate(2);
+        let mut bytes = Vec::new();
+        table.write(&mut bytes).unwrap();
+        let table = PcPruningTable::read(&mut bytes.as_slice()).unwrap();
+
+        let mut board = Board::new(vec![Piece::J, Piece::I, Piece::O, Piece::J, Piece::I]);
+        assert_eq!(
+            Some(board.find_perfect_clear(2).is_some()),
+            table.has_perfect_clear(&board)
+        );
+        let solution = table.find_perfect_clear(&board).unwrap().unwrap();
+        for mv in solution {
+            assert!(board.gen_moves().contains(&mv));
+            board.make_move_in_place(mv);
+        }
+        assert!(board.board.iter().flatten().all(|cell| cell.is_none()));
+
+        // Broken files are errors, not panics or huge allocations
+        let mut header = bytes[..14].to_vec();
+        header[6..].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(PcPruningTable::read(&mut header.as_slice()).is_err());
+        let mut tall = bytes.clone();
+        tall[5] = MAX_PC_HEIGHT as u8 + 1;
+        assert!(PcPruningTable::read(&mut tall.as_slice()).is_err());
+        let mut long_key = bytes[..14].to_vec();
+        long_key.extend([0xff; 11]);
+        let error = PcPruningTable::read(&mut long_key.as_slice())
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn boards_the_table_doesnt_cover() {
+        let table = PcPruningTable::generate(2);
+        // Covered, but the T leaves an odd number of cells in each line for the Os
+        let board = Board::new(vec![Piece::O, Piece::O, Piece::O, Piece::O, Piece::T]);
+        assert!(table.covers(&board));
+        assert_eq!(Some(false), table.has_perfect_clear(&board));
+
+        // Nothing can float like this on the way from an empty board, but an I can still slide
+        // under it and leave room for the Os
+        let mut board = Board::new(vec![Piece::I, Piece::O, Piece::O, Piece::O]);
+        board.fill(1..2, 0..4);
+        assert!(board.find_perfect_clear(2).is_some());
+        assert!(!table.covers(&board));
+        assert_eq!(None, table.has_perfect_clear(&board));
+
+        // Anything above the table's height is left to the runtime solver too
+        let mut board = Board::new(vec![Piece::O]);
+        board.fill(0..2, 0..8);
+        assert_eq!(Some(true), table.has_perfect_clear(&board));
+        board.fill(2..3, 0..1);
+        assert!(!table.covers(&board));
+        assert_eq!(None, table.has_perfect_clear(&board));
+    }
+}
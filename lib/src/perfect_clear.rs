@@ -0,0 +1,239 @@
+use crate::randomizer::*;
+use crate::tetris::*;
+use rustc_hash::FxHashSet;
+
+// Fields are packed into a u64 with bit y * 10 + x set for each filled cell, so only 6 rows fit
+pub const MAX_PC_HEIGHT: usize = 6;
+
+// A position as far as pcs care: the field, its height, the hold and how far into the queue it is
+pub(crate) type PcKey = (u64, usize, Option<Piece>, usize);
+
+struct PcSearch {
+    first_only: bool,
+    moves: Vec<Move>,
+    solutions: Vec<Vec<Move>>,
+    // Positions we already know can't be perfect cleared
+    dead: FxHashSet<PcKey>,
+}
+
+impl PcSearch {
+    fn new(first_only: bool) -> Self {
+        PcSearch {
+            first_only,
+            moves: Vec::new(),
+            solutions: Vec::new(),
+            dead: FxHashSet::default(),
+        }
+    }
+
+    fn search(&mut self, board: &mut Board, height: usize) -> bool {
+        let field = board.field_bits(height);
+        if field == 0 && !self.moves.is_empty() {
+            self.solutions.push(self.moves.clone());
+            return true;
+        }
+        if !enough_pieces(board, field, height) || !pc_possible(board, field, height) {
+            return false;
+        }
+        let key = (field, height, board.hold, board.queue_cursor);
+        if self.dead.contains(&key) {
+            return false;
+        }
+
+        let mut found = false;
+        for mv in distinct_placements(board.gen_moves()) {
+            if !fits_below(&mv, height) {
+                continue;
+            }
+            let undo = board.apply(mv);
+            self.moves.push(mv);
+            let cleared = self.search(board, height - undo.lines_cleared as usize);
+            self.moves.pop();
+            board.undo(undo);
+            if cleared {
+                found = true;
+                if self.first_only {
+                    return true;
+                }
+            }
+        }
+
+        if !found {
+            self.dead.insert(key);
+        }
+        found
+    }
+}
+
+fn enough_pieces(board: &Board, field: u64, height: usize) -> bool {
+    let empty = height * 10 - field.count_ones() as usize;
+    empty / 4 <= board.next_pieces().len() + board.hold.is_some() as usize
+}
+
+// Cheap checks that rule out most fields before any move gen happens
+pub(crate) fn pc_possible(board: &Board, field: u64, height: usize) -> bool {
+    // Anything sticking out above the pc height can never be cleared
+    if (height..40).any(|y| board.board[y].iter().any(|cell| cell.is_some())) {
+        return false;
+    }
+
+    let empty = height * 10 - field.count_ones() as usize;
+    if !empty.is_multiple_of(4) {
+        return false;
+    }
+
+    // Every separate pocket of empty cells has to be filled with whole pieces
+    let mut seen = field;
+    for start in 0..height * 10 {
+        if seen & (1 << start) != 0 {
+            continue;
+        }
+        seen |= 1 << start;
+        let mut stack = vec![start];
+        let mut size = 0usize;
+        while let Some(cell) = stack.pop() {
+            size += 1;
+            let (x, y) = (cell % 10, cell / 10);
+            let mut neighbours = Vec::with_capacity(4);
+            if x > 0 {
+                neighbours.push(cell - 1);
+            }
+            if x < 9 {
+                neighbours.push(cell + 1);
+            }
+            if y > 0 {
+                neighbours.push(cell - 10);
+            }
+            if y + 1 < height {
+                neighbours.push(cell + 10);
+            }
+            for neighbour in neighbours {
+                if seen & (1 << neighbour) == 0 {
+                    seen |= 1 << neighbour;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        if !size.is_multiple_of(4) {
+            return false;
+        }
+    }
+    true
+}
+
+#[inline]
+pub(crate) fn fits_below(mv: &Move, height: usize) -> bool {
+    mv.location
+        .cells()
+        .iter()
+        .all(|&(_, y)| (y as usize) < height)
+}
+
+// gen_moves gives the same cells more than once (S/Z/I orientations, spins, hold being the same
+// piece) which doesn't matter for pcs and blows up the search
+pub(crate) fn distinct_placements(moves: Vec<Move>) -> Vec<Move> {
+    let mut seen = FxHashSet::default();
+    moves
+        .into_iter()
+        .filter(|mv| {
+            let mut cells = mv.location.cells();
+            cells.sort_unstable();
+            seen.insert((mv.location.piece, cells))
+        })
+        .collect()
+}
+
+impl Board {
+    pub(crate) fn field_bits(&self, height: usize) -> u64 {
+        let mut field = 0;
+        for y in 0..height {
+            for x in 0..10 {
+                if self.board[y][x].is_some() {
+                    field |= 1 << (y * 10 + x);
+                }
+            }
+        }
+        field
+    }
+
+    // The first sequence of moves that clears the whole board without going above `height` rows.
+    // Heights over MAX_PC_HEIGHT never have one.
+    pub fn find_perfect_clear(&self, height: usize) -> Option<Vec<Move>> {
+        if height > MAX_PC_HEIGHT {
+            return None;
+        }
+        let mut search = PcSearch::new(true);
+        search.search(&mut self.clone(), height);
+        search.solutions.pop()
+    }
+
+    // Every pc within `height` rows using the visible queue and hold
+    pub fn find_perfect_clears(&self, height: usize) -> Vec<Vec<Move>> {
+        if height > MAX_PC_HEIGHT {
+            return Vec::new();
+        }
+        let mut search = PcSearch::new(false);
+        search.search(&mut self.clone(), height);
+        search.solutions
+    }
+
+    // How likely it is that there is a pc, filling in pieces past the queue with the randomizer.
+    // This assumes every piece will be visible before it has to be placed, and solves every
+    // possible queue one by one so it gets slow quickly.
+    pub fn perfect_clear_chance(&self, randomizer: &Randomizer, height: usize) -> f32 {
+        if height > MAX_PC_HEIGHT {
+            return 0.0;
+        }
+        let filled = self.field_bits(height).count_ones() as usize;
+        let needed = (height * 10 - filled) / 4;
+        let known = self.next_pieces().len() + self.hold.is_some() as usize;
+
+        randomizer
+            .continuations(needed.saturating_sub(known))
+            .into_iter()
+            .filter(|continuation| {
+                let mut board = self.clone();
+                board.queue.extend(&continuation.pieces);
+                board.find_perfect_clear(height).is_some()
+            })
+            .map(|continuation| continuation.probability)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn two_line_pc() {
+        let mut board = Board::new(vec![]);
+        board.fill(0..2, 0..8);
+        assert_eq!(None, board.find_perfect_clear(2));
+
+        let solution = board
+            .with_next_piece(Piece::O)
+            .find_perfect_clear(2)
+            .unwrap();
+        assert_eq!(1, solution.len());
+        assert_eq!(Piece::O, solution[0].location.piece);
+        assert!(board
+            .with_next_piece(Piece::T)
+            .find_perfect_clears(2)
+            .is_empty());
+
+        let randomizer = Randomizer::SevenBag {
+            bag_state: vec![Piece::O, Piece::I, Piece::T, Piece::S],
+        };
+        assert_eq!(0.25, board.perfect_clear_chance(&randomizer, 2));
+
+        // Too tall to search is the same as no pc, not a panic
+        let o = board.with_next_piece(Piece::O);
+        assert_eq!(None, o.find_perfect_clear(MAX_PC_HEIGHT + 1));
+        assert!(o.find_perfect_clears(MAX_PC_HEIGHT + 1).is_empty());
+        assert_eq!(
+            0.0,
+            board.perfect_clear_chance(&randomizer, MAX_PC_HEIGHT + 1)
+        );
+    }
+}
@@ -0,0 +1,250 @@
+extern crate serde;
+
+use crate::tetris::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const PIECES: [Piece; 7] = [
+    Piece::I,
+    Piece::O,
+    Piece::T,
+    Piece::L,
+    Piece::J,
+    Piece::S,
+    Piece::Z,
+];
+
+// The state of the randomizer *after* the last piece in the queue. The serde representation
+// matches the tbp randomizer extension so it can be read straight out of a start message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Randomizer {
+    Uniform,
+    // bag_state is the pieces still left in the current bag, in the same order as PIECES. A bag
+    // that runs out is full again straight away, so a fresh bag is always all seven. Frontends
+    // can send an empty bag for that too, see normalized.
+    SevenBag { bag_state: Vec<Piece> },
+}
+
+// A chance node: the next piece could be `piece` with this probability, and then the randomizer
+// would be in this state
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChanceOutcome {
+    pub piece: Piece,
+    pub probability: f32,
+    pub randomizer: Randomizer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueContinuation {
+    pub pieces: Vec<Piece>,
+    pub probability: f32,
+    pub randomizer: Randomizer,
+}
+
+impl Default for Randomizer {
+    fn default() -> Self {
+        Randomizer::SevenBag {
+            bag_state: PIECES.to_vec(),
+        }
+    }
+}
+
+impl Randomizer {
+    // The same randomizer in the form everything here makes them in, so equal states compare
+    // and hash equal however they were written down
+    pub fn normalized(self) -> Randomizer {
+        match self {
+            Randomizer::SevenBag { bag_state } if bag_state.is_empty() => Randomizer::default(),
+            Randomizer::SevenBag { bag_state } => Randomizer::SevenBag {
+                bag_state: PIECES
+                    .into_iter()
+                    .filter(|p| bag_state.contains(p))
+                    .collect(),
+            },
+            Randomizer::Uniform => Randomizer::Uniform,
+        }
+    }
+
+    pub fn possible_pieces(&self) -> Vec<Piece> {
+        match self {
+            Randomizer::Uniform => PIECES.to_vec(),
+            Randomizer::SevenBag { bag_state } if bag_state.is_empty() => PIECES.to_vec(),
+            Randomizer::SevenBag { bag_state } => bag_state.clone(),
+        }
+    }
+
+    pub fn outcomes(&self) -> Vec<ChanceOutcome> {
+        let pieces = self.possible_pieces();
+        let probability = 1.0 / pieces.len() as f32;
+        pieces
+            .into_iter()
+            .map(|piece| {
+                let mut randomizer = self.clone();
+                randomizer.take(piece);
+                ChanceOutcome {
+                    piece,
+                    probability,
+                    randomizer,
+                }
+            })
+            .collect()
+    }
+
+    // Best guess at the 7-bag state after `queue` when the frontend doesn't tell us. The queue
+    // gets split into bags at each possible offset and the first split where no bag has a repeat
+    // wins, which is right at the start of a game and usually right after that.
+    pub fn infer_seven_bag(queue: &[Piece]) -> Randomizer {
+        fn no_repeats(bag: &[Piece]) -> bool {
+            (0..bag.len()).all(|i| !bag[..i].contains(&bag[i]))
+        }
+
+        for offset in 0..=queue.len().min(6) {
+            // The first `offset` pieces are the end of a bag that started before the queue
+            let (end_of_bag, rest) = queue.split_at(offset);
+            if !no_repeats(end_of_bag) || !rest.chunks(7).all(no_repeats) {
+                continue;
+            }
+            let bag_state = match rest.chunks(7).last() {
+                Some(bag) if bag.len() < 7 => {
+                    PIECES.into_iter().filter(|p| !bag.contains(p)).collect()
+                }
+                _ => PIECES.to_vec(),
+            };
+            return Randomizer::SevenBag { bag_state };
+        }
+        Randomizer::default()
+    }
+
+    // Actually roll the next piece, for when we are the ones running the game
+    pub fn next_piece<R: Rng>(&mut self, rng: &mut R) -> Piece {
+        let pieces = self.possible_pieces();
+        let piece = pieces[rng.gen_range(0..pieces.len())];
+        self.take(piece);
+        piece
+    }
+
+    // Update the state after `piece` comes out of the randomizer. False if it couldn't have come
+    // out, which means we lost track of the bag somewhere. The bag gets restarted from `piece`
+    // then, since that's the most likely way for it to happen.
+    pub fn take(&mut self, piece: Piece) -> bool {
+        let Randomizer::SevenBag { bag_state } = self else {
+            return true;
+        };
+        if bag_state.is_empty() {
+            *bag_state = PIECES.to_vec();
+        }
+        match bag_state.iter().position(|&p| p == piece) {
+            Some(i) => {
+                bag_state.remove(i);
+                if bag_state.is_empty() {
+                    *bag_state = PIECES.to_vec();
+                }
+                true
+            }
+            None => {
+                *bag_state = PIECES.into_iter().filter(|&p| p != piece).collect();
+                false
+            }
+        }
+    }
+
+    // Every way the next `pieces` pieces could come out, along with how likely they are. This
+    // grows like 7^pieces so keep it small!
+    pub fn continuations(&self, pieces: usize) -> Vec<QueueContinuation> {
+        let mut continuations = vec![QueueContinuation {
+            pieces: Vec::with_capacity(pieces),
+            probability: 1.0,
+            randomizer: self.clone(),
+        }];
+        for _ in 0..pieces {
+            let mut next = Vec::with_capacity(continuations.len() * 7);
+            for continuation in continuations {
+                for outcome in continuation.randomizer.outcomes() {
+                    let mut pieces = continuation.pieces.clone();
+                    pieces.push(outcome.piece);
+                    next.push(QueueContinuation {
+                        pieces,
+                        probability: continuation.probability * outcome.probability,
+                        randomizer: outcome.randomizer,
+                    });
+                }
+            }
+            continuations = next;
+        }
+        continuations
+    }
+}
+
+impl Board {
+    // The board with `piece` added to the end of the queue
+    pub fn with_next_piece(&self, piece: Piece) -> Board {
+        let mut board = self.clone();
+        board.queue.push(piece);
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn bag_continuations() {
+        let board = Board::new(vec![]);
+        // Two pieces left in the bag, then a fresh one
+        let randomizer = Randomizer::SevenBag {
+            bag_state: vec![Piece::T, Piece::I],
+        };
+        let continuations = randomizer.continuations(3);
+        assert_eq!(2 * 7, continuations.len());
+        let total: f32 = continuations.iter().map(|c| c.probability).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+        for continuation in continuations {
+            assert_ne!(continuation.pieces[0], continuation.pieces[1]);
+            assert_eq!(6, continuation.randomizer.possible_pieces().len());
+        }
+        assert!(board.gen_moves().is_empty());
+
+        // An S can't come out of this bag, so it must have started a new one
+        let mut randomizer = Randomizer::SevenBag {
+            bag_state: vec![Piece::T, Piece::I],
+        };
+        assert!(randomizer.take(Piece::I));
+        assert!(!randomizer.take(Piece::S));
+        assert_eq!(6, randomizer.possible_pieces().len());
+        assert!(!randomizer.possible_pieces().contains(&Piece::S));
+    }
+
+    #[test]
+    fn infer_bag() {
+        use Piece::*;
+        let bag = |pieces: &[Piece]| Randomizer::SevenBag {
+            bag_state: pieces.to_vec(),
+        };
+        // Nothing seen yet, or exactly one bag, and the next piece starts a fresh one
+        assert_eq!(Randomizer::default(), Randomizer::infer_seven_bag(&[]));
+        assert_eq!(
+            Randomizer::default(),
+            Randomizer::infer_seven_bag(&[T, I, O, L, J, S, Z])
+        );
+        assert_eq!(bag(&[L, J, S, Z]), Randomizer::infer_seven_bag(&[T, I, O]));
+        // Into the second bag
+        assert_eq!(
+            bag(&[I, O, T, L, J]),
+            Randomizer::infer_seven_bag(&[S, Z, T, I, O, L, J, S, Z])
+        );
+        // A repeat means the queue started partway through a bag
+        assert_eq!(
+            bag(&[I, O, L, J, S, Z]),
+            Randomizer::infer_seven_bag(&[T, T])
+        );
+
+        // However a fresh bag is reached it's the same state
+        let mut emptied = bag(&[Z]);
+        emptied.take(Z);
+        assert_eq!(Randomizer::default(), emptied);
+        assert_eq!(Randomizer::default(), bag(&[]).normalized());
+        assert_eq!(bag(&[I, S]), bag(&[S, I]).normalized());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::tetris::*;
+use std::io::{self, Write};
+
+const RESET: &str = "\x1b[0m";
+// Rows above the visible 20 that still get drawn, pieces spawn up here
+const BUFFER_ROWS: usize = 3;
+// How many pieces of the queue get drawn, starting with the current one
+const QUEUE_SHOWN: usize = 6;
+
+fn color(cell: char) -> &'static str {
+    match cell {
+        'I' => "\x1b[46m",
+        'O' => "\x1b[43m",
+        'T' => "\x1b[45m",
+        'L' => "\x1b[48;5;208m",
+        'J' => "\x1b[44m",
+        'S' => "\x1b[42m",
+        'Z' => "\x1b[41m",
+        // Garbage, or anything else a frontend decided to put in the board
+        _ => "\x1b[100m",
+    }
+}
+
+fn block(cell: char) -> String {
+    format!("{}  {}", color(cell), RESET)
+}
+
+// A piece on its own in its spawn orientation, 2 rows that are 8 columns wide
+fn small_piece(piece: Option<Piece>) -> [String; 2] {
+    let mut rows = [String::new(), String::new()];
+    for (row, dy) in rows.iter_mut().zip([1, 0]) {
+        for dx in -1..=2 {
+            match piece {
+                Some(piece)
+                    if piece != Piece::G
+                        && piece.cells(&Orientation::North).contains(&(dx, dy)) =>
+                {
+                    row.push_str(&block(piece.to_char()))
+                }
+                _ => row.push_str("  "),
+            }
+        }
+    }
+    rows
+}
+
+impl Board {
+    // Draw the board along with the hold piece, the queue, combo and b2b, and where `ghost` would
+    // end up if there is one. Don't point this at stdout from inside a bot, that's where tbp goes!
+    pub fn render<W: Write>(&self, out: &mut W, ghost: Option<&Move>) -> io::Result<()> {
+        let ghost_cells = ghost.map(|mv| mv.location.cells());
+
+        let mut left = vec!["  HOLD    ".to_string()];
+        for row in small_piece(self.hold) {
+            left.push(format!("  {}", row));
+        }
+
+        let mut right = vec!["  QUEUE".to_string()];
+        for &piece in self.next_pieces().iter().take(QUEUE_SHOWN) {
+            right.extend(small_piece(Some(piece)).map(|row| format!("  {}", row)));
+            right.push(String::new());
+        }
+
+        let mut middle = Vec::new();
+        for y in (0..20 + BUFFER_ROWS).rev() {
+            let (side, empty) = if y >= 20 { (" ", "  ") } else { ("|", " .") };
+            let mut line = side.to_string();
+            for x in 0..10 {
+                let in_ghost = ghost_cells.is_some_and(|cells| cells.contains(&(x, y as i8)));
+                match self.board[y][x as usize] {
+                    Some(cell) => line.push_str(&block(cell)),
+                    None if in_ghost => line.push_str("\x1b[90m[]\x1b[0m"),
+                    None => line.push_str(empty),
+                }
+            }
+            line.push_str(side);
+            middle.push(line);
+            if y == 20 {
+                // Everything under here is the visible board
+                middle.push(" - - - - - - - - - -  ".to_string());
+            }
+        }
+        middle.push("+--------------------+".to_string());
+        let mut status = format!(" combo {}", self.combo);
+        if self.back_to_back {
+            status.push_str("  b2b");
+        }
+        middle.push(status);
+
+        for (i, line) in middle.iter().enumerate() {
+            let left = left.get(i).map_or("          ", |s| s.as_str());
+            let right = right.get(i).map_or("", |s| s.as_str());
+            writeln!(out, "{}{}{}", left, line, right)?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BUFFER_ROWS;
+    use crate::*;
+
+    #[test]
+    fn render_board() {
+        let mut board = Board {
+            back_to_back: true,
+            combo: 2,
+            hold: Some(Piece::T),
+            ..Board::new(vec![Piece::I, Piece::O])
+        };
+        board.board[0][0] = Some('G');
+        let ghost = board.gen_moves()[0];
+        let mut out = Vec::new();
+        board.render(&mut out, Some(&ghost)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        // Buffer, marker, visible rows, floor and status
+        assert_eq!(BUFFER_ROWS + 1 + 20 + 2, lines.len());
+        assert!(lines[0].contains("HOLD") && lines[0].contains("QUEUE"));
+        assert!(lines.last().unwrap().contains("combo 2  b2b"));
+        assert!(text.contains("[]"));
+        // Garbage in the bottom left corner
+        assert!(lines[lines.len() - 3].starts_with("          |\x1b[100m  "));
+    }
+}
@@ -0,0 +1,262 @@
+extern crate serde;
+
+use crate::randomizer::*;
+use crate::tbp::*;
+use crate::tetris::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+// Replays are json lines, one event per line, so a game that crashes halfway still leaves
+// everything up to the crash behind
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ReplayEvent {
+    // A new game (or a restart from a new position), the board replaces whatever came before
+    Start {
+        board: TBPBoard,
+        // Only there when we know how the pieces are being made, like in the simulator
+        seed: Option<u64>,
+        randomizer: Option<Randomizer>,
+    },
+    NewPiece {
+        piece: Piece,
+    },
+    Move {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    // Garbage coming in under the stack, with the hole for each line in the order they went in
+    Garbage {
+        holes: Vec<u8>,
+    },
+    End {
+        reason: String,
+    },
+}
+
+impl ReplayEvent {
+    // What a bot should write to its replay when it gets this message, if anything
+    pub fn from_frontend(message: &FrontendMessage) -> Option<ReplayEvent> {
+        match message {
+            FrontendMessage::Start(board) => Some(ReplayEvent::Start {
+                board: board.clone(),
+                seed: None,
+                randomizer: board.randomizer.clone(),
+            }),
+            FrontendMessage::NewPiece { piece } => Some(ReplayEvent::NewPiece { piece: *piece }),
+            FrontendMessage::Play { mv } => Some(ReplayEvent::Move { mv: *mv }),
+            FrontendMessage::Stop => Some(ReplayEvent::End {
+                reason: "stop".to_string(),
+            }),
+            FrontendMessage::Quit => Some(ReplayEvent::End {
+                reason: "quit".to_string(),
+            }),
+            FrontendMessage::Rules(_) | FrontendMessage::Suggest => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayEntry {
+    // Milliseconds since the replay started
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: ReplayEvent,
+}
+
+pub struct ReplayWriter<W: Write> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(out: W) -> Self {
+        ReplayWriter {
+            out,
+            start: Instant::now(),
+        }
+    }
+
+    // Flushes after every event so nothing is lost if we crash
+    pub fn write(&mut self, event: ReplayEvent) -> io::Result<()> {
+        let entry = ReplayEntry {
+            time: self.start.elapsed().as_millis() as u64,
+            event,
+        };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+impl ReplayWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ReplayWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+pub struct Replay {
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl Replay {
+    // Moves have to be playable in the position the replay is in when they come up, so anything
+    // that reads fine can be played back with boards
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        let mut board = None;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ReplayEntry = serde_json::from_str(&line)?;
+            if let Err(reason) = play_back(&mut board, &entry.event) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", number + 1, reason),
+                ));
+            }
+            entries.push(entry);
+        }
+        Ok(Replay { entries })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    // The position the player was looking at for every move, and then the position at the end.
+    // A start event starts over from its own board. Playback stops at anything that couldn't
+    // have happened, which read doesn't let through.
+    pub fn boards(&self) -> Vec<Board> {
+        let mut boards = Vec::new();
+        let mut board = None;
+        for entry in &self.entries {
+            match play_back(&mut board, &entry.event) {
+                Ok(done) => boards.extend(done),
+                Err(_) => break,
+            }
+        }
+        boards.extend(board);
+        boards
+    }
+}
+
+// Moves `board` along by one event. Gives back the position a move was made from, or the last one
+// before a start replaces it. Events before the first start have nothing to happen to.
+fn play_back(board: &mut Option<Board>, event: &ReplayEvent) -> Result<Option<Board>, String> {
+    if let ReplayEvent::Garbage { holes } = event {
+        if holes.iter().any(|&hole| hole >= 10) {
+            return Err("a garbage hole is off the board".to_string());
+        }
+    }
+    if let ReplayEvent::Start { board: start, .. } = event {
+        return Ok(board.replace(Board::from_tbp(start.clone())));
+    }
+    let Some(current) = board else {
+        return Ok(None);
+    };
+    match event {
+        ReplayEvent::NewPiece { piece } => current.queue.push(*piece),
+        ReplayEvent::Move { mv } => {
+            let Some(mv) = current.legal_move(*mv) else {
+                return Err(format!("{:?} can't be played there", mv.location));
+            };
+            let before = current.clone();
+            current.make_move_in_place(mv);
+            return Ok(Some(before));
+        }
+        ReplayEvent::Garbage { holes } => {
+            for &hole in holes {
+                current.insert_garbage(hole as usize);
+            }
+        }
+        ReplayEvent::Start { .. } | ReplayEvent::End { .. } => {}
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::FirstMove;
+    use crate::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::{self, BufWriter};
+
+    #[test]
+    fn replay_simulated_game() {
+        let path = env::temp_dir().join(format!("tetris-replay-{}.jsonl", std::process::id()));
+        let config = GameConfig {
+            garbage_messiness: 0.5,
+            ..GameConfig::default()
+        };
+        let mut game = Game::new(config, 3);
+        game.record(BufWriter::new(File::create(&path).unwrap()));
+        game.add_garbage(2);
+        let mut turns = 0;
+        while game.step(&mut FirstMove).is_some() {
+            turns += 1;
+            if turns == 5 {
+                game.add_garbage(3);
+            }
+        }
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            replay.entries[0].event,
+            ReplayEvent::Start { seed: Some(3), .. }
+        ));
+        assert!(matches!(
+            replay.entries.last().unwrap().event,
+            ReplayEvent::End { .. }
+        ));
+        let boards = replay.boards();
+        assert_eq!(turns + 1, boards.len());
+        assert_eq!(&game.board, boards.last().unwrap());
+
+        let bad = r#"{"time":0,"type":"garbage","holes":[3,10]}"#;
+        let error = Replay::read(bad.as_bytes()).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn moves_that_cant_be_played() {
+        let read = |board: Board, location: Location| {
+            let events = [
+                ReplayEvent::Start {
+                    board: board.to_tbp(),
+                    seed: None,
+                    randomizer: None,
+                },
+                ReplayEvent::Move {
+                    mv: Move {
+                        location,
+                        spin: Spin::None,
+                    },
+                },
+            ];
+            let lines: Vec<String> = events
+                .into_iter()
+                .map(|event| serde_json::to_string(&ReplayEntry { time: 0, event }).unwrap())
+                .collect();
+            Replay::read(lines.join("\n").as_bytes())
+        };
+        let t = Board::new(vec![Piece::T]).gen_moves()[0].location;
+        let off_board = Location { x: 30, ..t };
+
+        // Out of pieces, and somewhere a piece can't go
+        let error = read(Board::new(vec![]), t).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        let error = read(Board::new(vec![Piece::T]), off_board).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+        let replay = read(Board::new(vec![Piece::T]), t).unwrap();
+        assert_eq!(2, replay.boards().len());
+    }
+}
@@ -0,0 +1,409 @@
+use crate::eval::*;
+use crate::randomizer::*;
+use crate::replay::*;
+use crate::tbp::*;
+use crate::tetris::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::io::Write;
+
+// Anything that can pick moves for a game run by the simulator
+pub trait Player {
+    // A move for the current piece on `board` (or the hold piece if the board can hold), or None
+    // to give up.
+    // `randomizer` is the state after the last piece in the queue.
+    fn next_move(&mut self, board: &Board, randomizer: &Randomizer) -> Option<Move>;
+    // Stats about the search behind the last move, for players that have them
+    fn move_info(&self) -> Option<&MoveInfo> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameConfig {
+    // How many pieces after the current one the player gets to see
+    pub preview: usize,
+    pub hold: bool,
+    pub randomizer: Randomizer,
+    // The game stops after this many pieces even if nobody tops out
+    pub max_pieces: u32,
+    // Chance the hole moves between two lines of the same garbage attack
+    pub garbage_messiness: f32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            preview: 5,
+            hold: true,
+            randomizer: Randomizer::default(),
+            max_pieces: 1000,
+            garbage_messiness: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEnd {
+    PieceLimit,
+    // The next piece has nowhere to spawn
+    BlockOut,
+    // A piece locked completely above the visible board
+    LockOut,
+    // Garbage pushed the stack out of the top of the board
+    GarbageOut,
+    Resigned,
+    // The player gave a move with no placement in gen_moves, or used hold when it is turned off
+    IllegalMove,
+}
+
+impl GameEnd {
+    pub fn topped_out(&self) -> bool {
+        !matches!(self, GameEnd::PieceLimit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameResult {
+    pub end: GameEnd,
+    pub pieces: u32,
+    pub lines: u32,
+    // Everything the placements were worth, before any of it got cancelled
+    pub attack: u32,
+    pub garbage_received: u32,
+}
+
+// What happened on one turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Turn {
+    pub placement: Placement,
+    // The attack left over after cancelling incoming garbage, which goes to the opponent
+    pub sent: u32,
+}
+
+pub struct Game {
+    pub board: Board,
+    pub config: GameConfig,
+    // The state after the last piece in the queue
+    pub randomizer: Randomizer,
+    // Garbage that hasn't gone in yet, oldest attack first
+    pub incoming: VecDeque<u32>,
+    pub end: Option<GameEnd>,
+    seed: u64,
+    rng: StdRng,
+    // Garbage holes come from their own rng, so two games with the same seed get the same pieces
+    // however much garbage each of them takes
+    garbage_rng: StdRng,
+    replay: Option<ReplayWriter<Box<dyn Write + Send>>>,
+    pieces: u32,
+    lines: u32,
+    attack: u32,
+    garbage_received: u32,
+}
+
+impl Game {
+    pub fn new(config: GameConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut randomizer = config.randomizer.clone();
+        let queue = (0..=config.preview)
+            .map(|_| randomizer.next_piece(&mut rng))
+            .collect();
+        Game {
+            board: Board {
+                can_hold: config.hold,
+                ..Board::new(queue)
+            },
+            config,
+            randomizer,
+            incoming: VecDeque::new(),
+            end: None,
+            seed,
+            rng,
+            garbage_rng: StdRng::seed_from_u64(!seed),
+            replay: None,
+            pieces: 0,
+            lines: 0,
+            attack: 0,
+            garbage_received: 0,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.end.is_some()
+    }
+
+    pub fn result(&self) -> GameResult {
+        GameResult {
+            end: self.end.unwrap_or(GameEnd::PieceLimit),
+            pieces: self.pieces,
+            lines: self.lines,
+            attack: self.attack,
+            garbage_received: self.garbage_received,
+        }
+    }
+
+    // Write a replay of the game from here on. If writing ever fails the game carries on without
+    // a replay.
+    pub fn record<W: Write + Send + 'static>(&mut self, out: W) {
+        self.replay = Some(ReplayWriter::new(Box::new(out)));
+        self.write_replay(ReplayEvent::Start {
+            board: self.board.to_tbp(),
+            seed: Some(self.seed),
+            randomizer: Some(self.randomizer.clone()),
+        });
+    }
+
+    fn write_replay(&mut self, event: ReplayEvent) {
+        if let Some(replay) = &mut self.replay {
+            if let Err(e) = replay.write(event) {
+                eprintln!("Couldn't write to the replay, giving up on it: {}", e);
+                self.replay = None;
+            }
+        }
+    }
+
+    fn finish(&mut self, end: GameEnd) {
+        self.end = Some(end);
+        self.write_replay(ReplayEvent::End {
+            reason: format!("{:?}", end),
+        });
+    }
+
+    // Queue up garbage to go in the next time a piece doesn't clear a line
+    pub fn add_garbage(&mut self, lines: u32) {
+        if lines > 0 {
+            self.incoming.push_back(lines);
+        }
+    }
+
+    // Ask the player for a move and play it. None once the game is over.
+    pub fn step<P: Player + ?Sized>(&mut self, player: &mut P) -> Option<Turn> {
+        if self.end.is_none() && self.pieces >= self.config.max_pieces {
+            self.finish(GameEnd::PieceLimit);
+        }
+        if self.end.is_some() {
+            return None;
+        }
+
+        let Some(mv) = player.next_move(&self.board, &self.randomizer) else {
+            self.finish(GameEnd::Resigned);
+            return None;
+        };
+        // Played with the spin from gen_moves, which is also where the hold rule comes in
+        let Some(mv) = self.board.legal_move(mv) else {
+            self.finish(GameEnd::IllegalMove);
+            return None;
+        };
+
+        let undo = self.board.apply(mv);
+        let placement = Placement::new(&self.board, &undo);
+        self.board.forget_played_pieces();
+        self.write_replay(ReplayEvent::Move { mv });
+        self.pieces += 1;
+        self.lines += placement.lines_cleared as u32;
+        self.attack += placement.attack;
+
+        // Attack cancels incoming garbage before anything gets sent
+        let mut sent = placement.attack;
+        while sent > 0 {
+            let Some(lines) = self.incoming.front_mut() else {
+                break;
+            };
+            let cancelled = sent.min(*lines);
+            sent -= cancelled;
+            *lines -= cancelled;
+            if *lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+
+        let mut end = None;
+        if mv.locks_out() {
+            end = Some(GameEnd::LockOut);
+        } else if placement.lines_cleared == 0 && !self.take_garbage() {
+            end = Some(GameEnd::GarbageOut);
+        }
+
+        while self.board.next_pieces().len() <= self.config.preview {
+            let piece = self.randomizer.next_piece(&mut self.rng);
+            self.board.queue.push(piece);
+            self.write_replay(ReplayEvent::NewPiece { piece });
+        }
+        if end.is_none() && self.board.blocked_out() {
+            end = Some(GameEnd::BlockOut);
+        }
+        if let Some(end) = end {
+            self.finish(end);
+        }
+
+        Some(Turn { placement, sent })
+    }
+
+    // Push all the incoming garbage in under the stack, false if that pushes something out the top
+    fn take_garbage(&mut self) -> bool {
+        let mut holes = Vec::new();
+        let mut fits = true;
+        'attacks: while let Some(lines) = self.incoming.pop_front() {
+            self.garbage_received += lines;
+            let mut hole = self.garbage_rng.gen_range(0..10);
+            for _ in 0..lines {
+                if !self.board.insert_garbage(hole) {
+                    fits = false;
+                    break 'attacks;
+                }
+                holes.push(hole as u8);
+                if self.garbage_rng.gen::<f32>() < self.config.garbage_messiness {
+                    hole = self.garbage_rng.gen_range(0..10);
+                }
+            }
+        }
+        if !holes.is_empty() {
+            self.write_replay(ReplayEvent::Garbage { holes });
+        }
+        fits
+    }
+}
+
+// What searches score a line of play that tops out with, way below anything an evaluator gives
+pub const TOPPED_OUT: f32 = -1000.0;
+
+impl Move {
+    // Locking completely above the visible board ends the game
+    pub fn locks_out(&self) -> bool {
+        self.location.cells().iter().all(|&(_, y)| y >= 20)
+    }
+}
+
+impl Board {
+    // The next piece has nowhere to spawn, which ends the game. False when there is no next piece.
+    pub fn blocked_out(&self) -> bool {
+        self.next_pieces()
+            .first()
+            .is_some_and(|&piece| self.collision(&Location::spawn(piece)))
+    }
+
+    // Push everything up a line and put a line of garbage with a hole in it at the bottom. False
+    // (and nothing happens) if that would push something out the top.
+    pub fn insert_garbage(&mut self, hole: usize) -> bool {
+        if self.board[39].iter().any(|cell| cell.is_some()) {
+            return false;
+        }
+        self.board.copy_within(0..39, 1);
+        self.board[0] = [Some(Piece::G.to_char()); 10];
+        self.board[0][hole] = None;
+        true
+    }
+}
+
+// Play one whole game on its own, with nobody sending garbage
+pub fn play_game<P: Player + ?Sized>(player: &mut P, config: GameConfig, seed: u64) -> GameResult {
+    let mut game = Game::new(config, seed);
+    while game.step(player).is_some() {}
+    game.result()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::*;
+
+    // Always takes the first move, which is never going to clear anything
+    pub(crate) struct FirstMove;
+
+    impl Player for FirstMove {
+        fn next_move(&mut self, board: &Board, _: &Randomizer) -> Option<Move> {
+            board.gen_moves().first().copied()
+        }
+    }
+
+    // Plays the same move whatever the board
+    struct Fixed(Move);
+
+    impl Player for Fixed {
+        fn next_move(&mut self, _: &Board, _: &Randomizer) -> Option<Move> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn seeded_games() {
+        let config = GameConfig {
+            max_pieces: 200,
+            ..GameConfig::default()
+        };
+        let result = play_game(&mut FirstMove, config.clone(), 7);
+        assert!(result.end.topped_out());
+        assert!(result.pieces < 200);
+        assert_eq!(result, play_game(&mut FirstMove, config, 7));
+    }
+
+    #[test]
+    fn garbage_and_hold_rules() {
+        let config = GameConfig {
+            hold: false,
+            ..GameConfig::default()
+        };
+        let mut game = Game::new(config, 1);
+        game.add_garbage(3);
+        let turn = game.step(&mut FirstMove).unwrap();
+        assert_eq!(0, turn.sent);
+        assert_eq!(3, game.result().garbage_received);
+        for row in &game.board.board[..3] {
+            assert_eq!(9, row.iter().filter(|cell| cell.is_some()).count());
+        }
+        assert_eq!(6, game.board.next_pieces().len());
+
+        // The player only gets moves for the current piece, and playing the hold piece anyway isn't
+        // allowed
+        let current = game.board.next_pieces()[0];
+        assert!(game
+            .board
+            .gen_moves()
+            .iter()
+            .all(|mv| mv.location.piece == current));
+        let mut with_hold = game.board.clone();
+        with_hold.can_hold = true;
+        let held = *with_hold
+            .gen_moves()
+            .iter()
+            .find(|mv| mv.location.piece != current)
+            .unwrap();
+        assert_eq!(None, game.step(&mut Fixed(held)));
+        assert_eq!(Some(GameEnd::IllegalMove), game.end);
+    }
+
+    #[test]
+    fn attack_cancels_garbage() {
+        // A tetris ready with a line left over, so it isn't a perfect clear
+        let mut game = Game::new(GameConfig::default(), 2);
+        game.board.fill(0..5, 1..10);
+        game.board.queue[0] = Piece::I;
+        game.add_garbage(1);
+        game.add_garbage(2);
+        let tetris = *game
+            .board
+            .gen_moves()
+            .iter()
+            .find(|mv| mv.location.cells().iter().all(|&(x, _)| x == 0))
+            .unwrap();
+        let turn = game.step(&mut Fixed(tetris)).unwrap();
+        assert_eq!(4, turn.placement.attack);
+        assert_eq!(1, turn.sent);
+        assert!(game.incoming.is_empty());
+        assert_eq!(0, game.result().garbage_received);
+    }
+
+    #[test]
+    fn spin_comes_from_gen_moves() {
+        let mut game = Game::new(GameConfig::default(), 3);
+        let mv = game.board.gen_moves()[0];
+        assert_eq!(Spin::None, mv.spin);
+        let spun = Move {
+            spin: Spin::Full,
+            ..mv
+        };
+        let turn = game.step(&mut Fixed(spun)).unwrap();
+        assert_eq!(mv, turn.placement.mv);
+        assert_eq!(None, game.end);
+    }
+}
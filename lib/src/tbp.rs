@@ -1,13 +1,17 @@
 extern crate serde;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, Write};
+use std::time::Duration;
 
-pub use crate::tetris::*;
+use crate::randomizer::*;
+use crate::tetris::*;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum FrontendMessage {
-    Rules,
+    Rules(GameRules),
     Start(TBPBoard),
     Stop,
     Suggest,
@@ -24,19 +28,95 @@ pub enum FrontendMessage {
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum BotMessage {
-    Error { reason: String },
+    Error {
+        reason: String,
+    },
     Ready,
     Info(BotInfo),
-    Suggestion { moves: Vec<Move> },
+    Suggestion {
+        moves: Vec<Move>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        move_info: Option<MoveInfo>,
+    },
+}
+
+// What went into a suggestion, so frontends can see how hard a bot is working. Everything is
+// optional since not every kind of search has nodes or a depth.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MoveInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    // The bot's own score for each move, in the same order as the moves
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evaluations: Vec<f32>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MoveInfo {
+    // Nodes along with how fast they were searched
+    pub fn with_nodes(nodes: u64, elapsed: Duration) -> Self {
+        MoveInfo {
+            nodes: Some(nodes),
+            nps: Some(nodes as f64 / elapsed.as_secs_f64().max(1e-9)),
+            ..MoveInfo::default()
+        }
+    }
+}
+
+// The error reason tbp has for rules a bot can't play by, so a frontend can tell that apart from
+// everything else that goes wrong
+pub const UNSUPPORTED_RULES: &str = "unsupported_rules";
+
+// Randomizers a bot here can work with, named like the tbp randomizer extension does
+pub const KNOWN_RANDOMIZERS: [&str; 2] = ["seven_bag", "uniform"];
+
+// The rules message. Anything in it we don't know about ends up in `extra` so a bot can still
+// look at it, or turn the rules down.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GameRules {
+    // From the randomizer extension, None when the frontend doesn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub randomizer: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Deserialize)]
+impl GameRules {
+    // The reason a bot can't play by these rules, if there is one. This is what bots check by
+    // default.
+    pub fn check(&self) -> Result<(), String> {
+        match &self.randomizer {
+            Some(randomizer) if !KNOWN_RANDOMIZERS.contains(&randomizer.as_str()) => {
+                Err(format!("unsupported randomizer {}", randomizer))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // What the randomizer could be after this queue. Without being told it's a 7 bag.
+    pub fn infer_randomizer(&self, queue: &[Piece]) -> Randomizer {
+        match self.randomizer.as_deref() {
+            Some("uniform") => Randomizer::Uniform,
+            _ => Randomizer::infer_seven_bag(queue),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TBPBoard {
     pub hold: Option<Piece>,
     pub queue: Vec<Piece>,
     pub combo: u32,
     pub back_to_back: bool,
     pub board: Vec<Vec<Option<char>>>,
+    // From the randomizer extension, only sent to bots that have it in their features
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub randomizer: Option<Randomizer>,
 }
 
 #[derive(Serialize)]
@@ -49,11 +129,18 @@ pub struct BotInfo {
 
 impl BotMessage {
     pub fn send_message(&self) {
+        self.write_to(&mut io::stdout())
+            .expect("couldn't write to stdout");
+    }
+
+    // One line, flushed straight away so the frontend isn't left waiting
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         let str = serde_json::to_string(self).unwrap();
         if str == "Null" {
             panic!("null thing!?!?");
         }
-        println!("{}", str);
+        writeln!(writer, "{}", str)?;
+        writer.flush()
     }
 }
 
@@ -62,3 +149,162 @@ pub fn get_frontend_message(input: String) -> serde_json::Result<FrontendMessage
 
     Ok(v)
 }
+
+// Every type of FrontendMessage, as it's written in json
+pub const FRONTEND_MESSAGE_TYPES: [&str; 7] = [
+    "rules",
+    "start",
+    "stop",
+    "suggest",
+    "play",
+    "new_piece",
+    "quit",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BadMessage {
+    // Not json, or a message we know about with something wrong in it
+    Invalid(String),
+    // A type of message we've never heard of, probably from a newer frontend
+    Unknown(String),
+}
+
+// Like get_frontend_message, but says whether a line that didn't parse was broken or just
+// something we don't know about. Extra fields on messages we do know are ignored, ignored_fields
+// says which ones there were.
+pub fn parse_frontend_message(input: &str) -> Result<FrontendMessage, BadMessage> {
+    let error = match serde_json::from_str(input) {
+        Ok(message) => return Ok(message),
+        Err(e) => e,
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(input).map_err(|e| BadMessage::Invalid(e.to_string()))?;
+    match value.get("type").and_then(|t| t.as_str()) {
+        Some(kind) if !FRONTEND_MESSAGE_TYPES.contains(&kind) => {
+            Err(BadMessage::Unknown(kind.to_string()))
+        }
+        _ => Err(BadMessage::Invalid(error.to_string())),
+    }
+}
+
+// The fields in `input` that didn't end up anywhere in the message read from it, like "time" or
+// "move.location.rotated". A field that's null counts as left out, so it isn't one of these.
+// This reads the line again and writes the message back out to compare them, so it's only worth
+// it when someone's going to look at the answer.
+pub fn ignored_fields(input: &str, message: &FrontendMessage) -> Vec<String> {
+    let (Ok(input), Ok(read)) = (
+        serde_json::from_str::<Value>(input),
+        serde_json::to_value(message),
+    ) else {
+        return Vec::new();
+    };
+    let mut ignored = Vec::new();
+    missing_fields(&input, &read, "", &mut ignored);
+    ignored
+}
+
+fn missing_fields(input: &Value, read: &Value, path: &str, ignored: &mut Vec<String>) {
+    let (Value::Object(input), Value::Object(read)) = (input, read) else {
+        return;
+    };
+    for (key, value) in input {
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match read.get(key) {
+            Some(read) => missing_fields(value, read, &path, ignored),
+            None if !value.is_null() => ignored.push(path),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde_json::json;
+
+    #[test]
+    fn rules_message() {
+        let message = get_frontend_message(r#"{"type":"rules"}"#.to_string()).unwrap();
+        let FrontendMessage::Rules(rules) = message else {
+            panic!("not a rules message");
+        };
+        assert_eq!(GameRules::default(), rules);
+
+        let line = r#"{"type":"rules","randomizer":"seven_bag","garbage":{"multiplier":2}}"#;
+        let FrontendMessage::Rules(rules) = get_frontend_message(line.to_string()).unwrap() else {
+            panic!("not a rules message");
+        };
+        assert_eq!(Some("seven_bag"), rules.randomizer.as_deref());
+        assert_eq!(json!({"multiplier": 2}), rules.extra["garbage"]);
+        assert_eq!(1, rules.extra.len());
+        assert!(rules.check().is_ok());
+        // Whatever we didn't understand goes back out the same way
+        let value = serde_json::to_value(FrontendMessage::Rules(rules)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(line).unwrap(),
+            value
+        );
+
+        let rules = GameRules {
+            randomizer: Some("classic".to_string()),
+            ..GameRules::default()
+        };
+        assert!(rules.check().is_err());
+        let uniform = GameRules {
+            randomizer: Some("uniform".to_string()),
+            ..GameRules::default()
+        };
+        assert_eq!(Randomizer::Uniform, uniform.infer_randomizer(&[Piece::T]));
+    }
+
+    #[test]
+    fn bad_messages() {
+        let line = r#"{"type":"play","move":{"location":{"type":"T","orientation":"north","x":4,"y":1,"kick":2},"spin":"none"},"time":3,"hold":null}"#;
+        let message = parse_frontend_message(line).unwrap();
+        assert!(matches!(message, FrontendMessage::Play { .. }));
+        assert_eq!(
+            vec!["move.location.kick", "time"],
+            ignored_fields(line, &message)
+        );
+        // The rules keep whatever they don't know about, so nothing gets ignored
+        let line = r#"{"type":"rules","randomizer":"seven_bag","garbage":{"multiplier":2}}"#;
+        let message = parse_frontend_message(line).unwrap();
+        assert!(ignored_fields(line, &message).is_empty());
+        assert_eq!(
+            Err(BadMessage::Unknown("hurry_up".to_string())),
+            parse_frontend_message(r#"{"type":"hurry_up","by":2}"#).map(|_| ())
+        );
+        for line in ["not json", r#"{"type":"play"}"#, r#"{"kind":"stop"}"#, "[]"] {
+            assert!(matches!(
+                parse_frontend_message(line),
+                Err(BadMessage::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn start_with_randomizer() {
+        let mut board = Board::new(vec![Piece::T, Piece::I]).to_tbp();
+        // Left out unless there's something to send, bots without the extension never see it
+        let line = serde_json::to_string(&FrontendMessage::Start(board.clone())).unwrap();
+        assert!(!line.contains("randomizer"));
+
+        board.randomizer = Some(Randomizer::SevenBag {
+            bag_state: vec![Piece::O, Piece::S],
+        });
+        let line = serde_json::to_string(&FrontendMessage::Start(board)).unwrap();
+        assert!(line.contains(r#""randomizer":{"type":"seven_bag","bag_state":["O","S"]}"#));
+        assert!(ignored_fields(&line, &parse_frontend_message(&line).unwrap()).is_empty());
+        let FrontendMessage::Start(board) = get_frontend_message(line).unwrap() else {
+            panic!("not a start message");
+        };
+        assert_eq!(
+            vec![Piece::O, Piece::S],
+            board.randomizer.unwrap().possible_pieces()
+        );
+    }
+}
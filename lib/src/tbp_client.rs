@@ -0,0 +1,608 @@
+extern crate serde;
+
+use crate::randomizer::*;
+use crate::sim::*;
+use crate::tbp::*;
+use crate::tetris::*;
+use crate::{run_bot_with, Bot};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::{self, pipe, BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// The frontend side of tbp, for running a bot in another process, or on another thread as if it
+// was one
+
+// BotMessage borrows everything so it can't be read back in, these own their strings instead
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BotDetails {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum BotReply {
+    Error {
+        reason: String,
+    },
+    Ready,
+    Info(BotDetails),
+    Suggestion {
+        moves: Vec<Move>,
+        #[serde(default)]
+        move_info: Option<MoveInfo>,
+    },
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    // The bot sent a line that isn't a message we know about
+    Json(serde_json::Error),
+    Timeout,
+    // The bot closed its stdout, probably because it exited
+    Closed,
+    // The bot sent an error message
+    Bot(String),
+    // The bot sent a message we weren't waiting for
+    Unexpected(BotReply),
+    // An earlier reply never came or couldn't be matched up, so there's no telling which request
+    // the next one is for
+    LostTrack,
+}
+
+// Whatever is running the bot, so it can be waited for after quit
+enum Running {
+    Process(Child),
+    Thread(JoinHandle<()>),
+}
+
+pub struct TbpClient {
+    pub info: BotDetails,
+    running: Running,
+    // Only None once the client is being dropped, which lets the bot see its stdin close
+    stdin: Option<Box<dyn Write + Send>>,
+    replies: Receiver<Result<BotReply, serde_json::Error>>,
+    // How long the bot gets to exit after quit before it gets killed, the same as it got for
+    // each reply in the handshake
+    timeout: Duration,
+    lost_track: bool,
+}
+
+impl TbpClient {
+    // Start the bot and go through the info/rules/ready handshake, giving it `timeout` for each
+    // reply
+    pub fn spawn<I, S>(program: &str, args: I, timeout: Duration) -> Result<Self, ClientError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        Self::connect(Running::Process(child), Box::new(stdin), stdout, timeout)
+    }
+
+    // Same as spawn, but the bot is `bot` on its own thread, reading the frontend's messages from
+    // the first pipe and writing its own to the second. The thread finishing counts as exiting.
+    pub fn in_process<F>(bot: F, timeout: Duration) -> Result<Self, ClientError>
+    where
+        F: FnOnce(PipeReader, PipeWriter) + Send + 'static,
+    {
+        let (bot_stdin, stdin) = pipe()?;
+        let (stdout, bot_stdout) = pipe()?;
+        let thread = thread::spawn(move || bot(bot_stdin, bot_stdout));
+        Self::connect(Running::Thread(thread), Box::new(stdin), stdout, timeout)
+    }
+
+    // One of our own bots on a thread, exactly as it would run as a program
+    pub fn from_bot<B: Bot>(
+        info: BotInfo,
+        settings: B::Settings,
+        timeout: Duration,
+    ) -> Result<Self, ClientError> {
+        Self::in_process(
+            move |stdin, stdout| run_bot_with::<B, _, _>(info, stdin, stdout, settings),
+            timeout,
+        )
+    }
+
+    fn connect<R: Read + Send + 'static>(
+        running: Running,
+        stdin: Box<dyn Write + Send>,
+        stdout: R,
+        timeout: Duration,
+    ) -> Result<Self, ClientError> {
+        // Reading happens on its own thread so waiting for a reply can time out
+        let (sender, replies) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if sender.send(serde_json::from_str(&line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = TbpClient {
+            info: BotDetails {
+                name: String::new(),
+                version: String::new(),
+                author: String::new(),
+                features: Vec::new(),
+            },
+            running,
+            stdin: Some(stdin),
+            replies,
+            timeout,
+            lost_track: false,
+        };
+        match client.recv_timeout(timeout)? {
+            BotReply::Info(info) => client.info = info,
+            reply => return Err(ClientError::Unexpected(reply)),
+        }
+        client.send(&FrontendMessage::Rules(GameRules::default()))?;
+        match client.recv_timeout(timeout)? {
+            BotReply::Ready => Ok(client),
+            reply => Err(ClientError::Unexpected(reply)),
+        }
+    }
+
+    pub fn send(&mut self, message: &FrontendMessage) -> Result<(), ClientError> {
+        self.send_line(&serde_json::to_string(message)?)
+    }
+
+    // Anything at all, for seeing what a bot does with messages it shouldn't get
+    pub fn send_line(&mut self, line: &str) -> Result<(), ClientError> {
+        let stdin = self.stdin.as_mut().ok_or(ClientError::Closed)?;
+        writeln!(stdin, "{}", line)?;
+        stdin.flush()?;
+        Ok(())
+    }
+
+    // The next message from the bot. Error messages come back as ClientError::Bot. Once one of
+    // these times out, a late reply could be taken for the answer to the next request, so every
+    // call after that gives ClientError::LostTrack instead.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<BotReply, ClientError> {
+        if self.lost_track {
+            return Err(ClientError::LostTrack);
+        }
+        match self.replies.recv_timeout(timeout) {
+            Ok(Ok(BotReply::Error { reason })) => Err(ClientError::Bot(reason)),
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(e)) => Err(ClientError::Json(e)),
+            Err(RecvTimeoutError::Timeout) => {
+                self.lost_track = true;
+                Err(ClientError::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(ClientError::Closed),
+        }
+    }
+
+    // The randomizer only gets sent if the bot says it supports the randomizer extension
+    pub fn start(
+        &mut self,
+        board: &Board,
+        randomizer: Option<&Randomizer>,
+    ) -> Result<(), ClientError> {
+        let mut tbp_board = board.to_tbp();
+        if self
+            .info
+            .features
+            .iter()
+            .any(|feature| feature == "randomizer")
+        {
+            tbp_board.randomizer = randomizer.cloned();
+        }
+        self.send(&FrontendMessage::Start(tbp_board))
+    }
+
+    pub fn new_piece(&mut self, piece: Piece) -> Result<(), ClientError> {
+        self.send(&FrontendMessage::NewPiece { piece })
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<(), ClientError> {
+        self.send(&FrontendMessage::Play { mv })
+    }
+
+    pub fn stop(&mut self) -> Result<(), ClientError> {
+        self.send(&FrontendMessage::Stop)
+    }
+
+    // Ask for the bot's moves, best first
+    pub fn suggest(&mut self, timeout: Duration) -> Result<Vec<Move>, ClientError> {
+        self.suggest_with_info(timeout).map(|(moves, _)| moves)
+    }
+
+    // Same as suggest but with whatever the bot said about its search. Errors the bot sent about
+    // earlier messages (a play it didn't like, a line it couldn't read) are skipped over first so
+    // they don't get taken for the answer.
+    pub fn suggest_with_info(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(Vec<Move>, Option<MoveInfo>), ClientError> {
+        if self.lost_track {
+            return Err(ClientError::LostTrack);
+        }
+        while let Ok(reply) = self.replies.try_recv() {
+            match reply {
+                Ok(BotReply::Error { .. }) | Err(_) => {}
+                // Nothing was asked for, so replies are out of step somehow
+                Ok(reply) => {
+                    self.lost_track = true;
+                    return Err(ClientError::Unexpected(reply));
+                }
+            }
+        }
+        self.send(&FrontendMessage::Suggest)?;
+        match self.recv_timeout(timeout) {
+            Ok(BotReply::Suggestion { moves, move_info }) => Ok((moves, move_info)),
+            Ok(reply) => Err(ClientError::Unexpected(reply)),
+            // Could be the answer, or about something sent just before the suggest that the bot
+            // only got to now, in which case the real answer is still coming
+            Err(ClientError::Bot(reason)) => {
+                self.lost_track = true;
+                Err(ClientError::Bot(reason))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Tell the bot to quit and wait for it to exit. A bot that's still running after the
+    // handshake timeout gets killed, and that's a ClientError::Timeout. Threads can't be killed,
+    // so one that's still going just gets left to it.
+    pub fn quit(mut self) -> Result<(), ClientError> {
+        match self.close()? {
+            true => Ok(()),
+            false => Err(ClientError::Timeout),
+        }
+    }
+
+    // False if the bot had to be killed
+    fn close(&mut self) -> io::Result<bool> {
+        if self.stdin.is_none() {
+            return Ok(true);
+        }
+        let _ = self.send(&FrontendMessage::Quit);
+        self.stdin = None;
+        let deadline = Instant::now() + self.timeout;
+        while Instant::now() < deadline {
+            if self.exited()? {
+                return Ok(true);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        // It might have only just exited, in which case there's nothing to kill
+        if self.exited()? {
+            return Ok(true);
+        }
+        if let Running::Process(child) = &mut self.running {
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(false)
+    }
+
+    fn exited(&mut self) -> io::Result<bool> {
+        match &mut self.running {
+            Running::Process(child) => Ok(child.try_wait()?.is_some()),
+            Running::Thread(thread) => Ok(thread.is_finished()),
+        }
+    }
+}
+
+impl Drop for TbpClient {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Json(e) => write!(f, "couldn't read the bot's message: {}", e),
+            ClientError::Timeout => write!(f, "the bot took too long to reply"),
+            ClientError::Closed => write!(f, "the bot closed its stdout"),
+            ClientError::Bot(reason) => write!(f, "the bot sent an error: {}", reason),
+            ClientError::Unexpected(reply) => write!(f, "unexpected message {:?}", reply),
+            ClientError::LostTrack => {
+                write!(
+                    f,
+                    "an earlier reply timed out or was ambiguous, so later ones can't be trusted"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+// A bot that talks tbp, in another process or on a thread
+pub struct TbpPlayer {
+    pub name: String,
+    // How long the bot gets to think before it is asked for a move
+    pub think_time: Duration,
+    // How long to wait for a reply before giving up on the bot
+    pub timeout: Duration,
+    client: TbpClient,
+    // What the bot thinks the board is, None before it has been started
+    bot_board: Option<Board>,
+    // What the bot said about its last suggestion
+    last_info: Option<MoveInfo>,
+}
+
+// How long a TbpPlayer waits for each reply
+const PLAYER_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl TbpPlayer {
+    pub fn spawn(command: &str, think_time: Duration) -> Result<Self, ClientError> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            ClientError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty bot command",
+            ))
+        })?;
+        let client = TbpClient::spawn(program, parts, PLAYER_TIMEOUT)?;
+        Ok(Self::new(client, think_time))
+    }
+
+    pub fn from_bot<B: Bot>(
+        info: BotInfo,
+        settings: B::Settings,
+        think_time: Duration,
+    ) -> Result<Self, ClientError> {
+        let client = TbpClient::from_bot::<B>(info, settings, PLAYER_TIMEOUT)?;
+        Ok(Self::new(client, think_time))
+    }
+
+    fn new(client: TbpClient, think_time: Duration) -> Self {
+        TbpPlayer {
+            name: format!("{} {}", client.info.name, client.info.version),
+            think_time,
+            timeout: PLAYER_TIMEOUT,
+            client,
+            bot_board: None,
+            last_info: None,
+        }
+    }
+
+    // Get the bot's idea of the board to match the real one, with new_piece if only the queue got
+    // longer and by starting over otherwise (like after garbage comes in)
+    fn sync(&mut self, board: &Board, randomizer: &Randomizer) -> Result<(), ClientError> {
+        if let Some(bot_board) = &self.bot_board {
+            let same = bot_board.board == board.board
+                && bot_board.hold == board.hold
+                && bot_board.combo == board.combo
+                && bot_board.back_to_back == board.back_to_back
+                && board.next_pieces().starts_with(bot_board.next_pieces());
+            if same {
+                let new_pieces = board.next_pieces()[bot_board.next_pieces().len()..].to_vec();
+                for piece in new_pieces {
+                    self.client.new_piece(piece)?;
+                }
+                self.bot_board = Some(board.clone());
+                return Ok(());
+            }
+            self.client.stop()?;
+        }
+
+        self.client.start(board, Some(randomizer))?;
+        self.bot_board = Some(board.clone());
+        Ok(())
+    }
+
+    fn suggest(
+        &mut self,
+        board: &Board,
+        randomizer: &Randomizer,
+    ) -> Result<Option<Move>, ClientError> {
+        self.sync(board, randomizer)?;
+        thread::sleep(self.think_time);
+        let (moves, info) = self.client.suggest_with_info(self.timeout)?;
+        self.last_info = info;
+        let Some(&mv) = moves.first() else {
+            return Ok(None);
+        };
+        // The game ends on an illegal move anyway, it just can't be played on our copy of the
+        // bot's board. That copy is left alone so the next game starts the bot over.
+        let Some(mv) = board.legal_move(mv) else {
+            return Ok(Some(mv));
+        };
+        self.client.play(mv)?;
+        let bot_board = self.bot_board.as_mut().unwrap();
+        bot_board.make_move_in_place(mv);
+        Ok(Some(mv))
+    }
+}
+
+impl Player for TbpPlayer {
+    fn next_move(&mut self, board: &Board, randomizer: &Randomizer) -> Option<Move> {
+        match self.suggest(board, randomizer) {
+            Ok(mv) => mv,
+            Err(e) => {
+                eprintln!("{}: {}", self.name, e);
+                None
+            }
+        }
+    }
+
+    fn move_info(&self) -> Option<&MoveInfo> {
+        self.last_info.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn scripted_bot() {
+        // A "bot" that only knows the handshake and one suggestion, then goes quiet
+        let script = r#"
+            echo '{"type":"info","name":"Script","version":"1","author":"sh","features":[]}'
+            read rules
+            echo '{"type":"ready"}'
+            read start
+            read suggest
+            echo '{"type":"suggestion","moves":[{"location":{"type":"I","orientation":"north","x":4,"y":0},"spin":"none"}],"move_info":{"nodes":10,"depth":2,"hash_hits":3}}'
+            read suggest_again
+            read quit
+        "#;
+        let timeout = Duration::from_secs(5);
+        let mut client = TbpClient::spawn("sh", ["-c", script], timeout).unwrap();
+        assert_eq!("Script", client.info.name);
+
+        let board = Board::new(vec![Piece::I, Piece::O]);
+        client.start(&board, None).unwrap();
+        let (moves, info) = client.suggest_with_info(timeout).unwrap();
+        let info = info.unwrap();
+        assert_eq!(
+            (Some(10), Some(2), None),
+            (info.nodes, info.depth, info.nps)
+        );
+        assert_eq!(3, info.extra["hash_hits"]);
+        assert_eq!(1, moves.len());
+        assert_eq!(Piece::I, moves[0].location.piece);
+        assert!(board.gen_moves().contains(&moves[0]));
+
+        assert!(matches!(
+            client.suggest(Duration::from_millis(50)),
+            Err(ClientError::Timeout)
+        ));
+        assert!(matches!(
+            client.suggest(timeout),
+            Err(ClientError::LostTrack)
+        ));
+        client.quit().unwrap();
+    }
+
+    #[test]
+    fn errors_before_a_suggest() {
+        // Complains about the play, then answers the suggest
+        let script = r#"
+            echo '{"type":"info","name":"Script","version":"1","author":"sh","features":[]}'
+            read rules
+            echo '{"type":"ready"}'
+            read start
+            read play
+            echo '{"type":"error","reason":"illegal move"}'
+            read suggest
+            echo '{"type":"suggestion","moves":[{"location":{"type":"O","orientation":"north","x":4,"y":0},"spin":"none"}]}'
+            read suggest_again
+            echo '{"type":"error","reason":"something broke"}'
+            read quit
+        "#;
+        let timeout = Duration::from_secs(5);
+        let mut client = TbpClient::spawn("sh", ["-c", script], timeout).unwrap();
+        let board = Board::new(vec![Piece::I, Piece::O]);
+        client.start(&board, None).unwrap();
+        client
+            .play(Move {
+                location: Location::spawn(Piece::T),
+                spin: Spin::None,
+            })
+            .unwrap();
+        // Give the error time to come in before the suggest goes out
+        std::thread::sleep(Duration::from_millis(100));
+        let moves = client.suggest(timeout).unwrap();
+        assert_eq!(Piece::O, moves[0].location.piece);
+
+        // An error after the suggest might be about something else, so nothing after it is trusted
+        assert!(matches!(client.suggest(timeout), Err(ClientError::Bot(_))));
+        assert!(matches!(
+            client.suggest(timeout),
+            Err(ClientError::LostTrack)
+        ));
+        client.quit().unwrap();
+    }
+
+    // Always wants to play the first move it can
+    struct FirstMoveBot;
+
+    impl Bot for FirstMoveBot {
+        type Settings = ();
+
+        fn new(_: Board, _: &GameRules, _: ()) -> Self {
+            FirstMoveBot
+        }
+
+        fn search(&self, status: &SearchStatus) {
+            loop {
+                let moves = status.board().gen_moves();
+                status.current_moves(&moves[..moves.len().min(1)]);
+                if !status.catch_up() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bot_on_a_thread() {
+        let info = BotInfo {
+            name: "First Move",
+            version: "1",
+            author: "test",
+            features: &[],
+        };
+        let timeout = Duration::from_secs(5);
+        let mut client = TbpClient::from_bot::<FirstMoveBot>(info, (), timeout).unwrap();
+        assert_eq!("First Move", client.info.name);
+
+        let board = Board::new(vec![Piece::T, Piece::I]);
+        client.start(&board, None).unwrap();
+        let moves = client.suggest(timeout).unwrap();
+        assert_eq!(vec![board.gen_moves()[0]], moves);
+        client.play(moves[0]).unwrap();
+        let moves = client.suggest(timeout).unwrap();
+        assert_eq!(Piece::I, moves[0].location.piece);
+        client.quit().unwrap();
+    }
+
+    #[test]
+    fn bot_that_wont_quit() {
+        // Does the handshake and then ignores everything
+        let script = r#"
+            echo '{"type":"info","name":"Stubborn","version":"1","author":"sh","features":[]}'
+            read rules
+            echo '{"type":"ready"}'
+            while true; do read line || sleep 1; done
+        "#;
+        let timeout = Duration::from_millis(200);
+        let client = TbpClient::spawn("sh", ["-c", script], timeout).unwrap();
+        let start = std::time::Instant::now();
+        assert!(matches!(client.quit(), Err(ClientError::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -5,8 +5,9 @@ use rustc_hash::FxHashSet;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
+use std::ops::Range;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Piece {
     O,
     I,
@@ -56,15 +57,33 @@ pub struct Location {
     pub y: i8,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Board {
     pub hold: Option<Piece>,
+    // False when the rules turn hold off, or the current piece already came out of a hold, and then
+    // gen_moves only has moves for the current piece
+    pub can_hold: bool,
+    // Pieces before queue_cursor have already been played, the current piece is
+    // queue[queue_cursor]
     pub queue: Vec<Piece>,
+    pub queue_cursor: usize,
     pub combo: u32,
     pub back_to_back: bool,
     pub board: [[Option<char>; 10]; 40],
 }
 
+// Everything Board::undo needs to take back a move made with Board::apply
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    pub mv: Move,
+    pub lines_cleared: u8,
+    cleared_rows: [(u8, [Option<char>; 10]); 4],
+    pub hold: Option<Piece>,
+    pub queue_cursor: usize,
+    pub combo: u32,
+    pub back_to_back: bool,
+}
+
 impl Hash for Move {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let hash = (match self.spin {
@@ -84,9 +103,43 @@ impl Hash for Move {
     }
 }
 
+impl Orientation {
+    #[inline]
+    pub fn rotate(&self, rotation: Rotation) -> Orientation {
+        match self {
+            Orientation::North => match rotation {
+                Rotation::Clockwise => Orientation::East,
+                Rotation::AntiClockwise => Orientation::West,
+            },
+            Orientation::East => match rotation {
+                Rotation::Clockwise => Orientation::South,
+                Rotation::AntiClockwise => Orientation::North,
+            },
+            Orientation::South => match rotation {
+                Rotation::Clockwise => Orientation::West,
+                Rotation::AntiClockwise => Orientation::East,
+            },
+            Orientation::West => match rotation {
+                Rotation::Clockwise => Orientation::North,
+                Rotation::AntiClockwise => Orientation::South,
+            },
+        }
+    }
+}
+
 impl Location {
+    // Where a piece shows up before it gets moved anywhere
+    pub fn spawn(piece: Piece) -> Location {
+        Location {
+            piece,
+            orientation: Orientation::North,
+            x: 5,
+            y: 19,
+        }
+    }
+
     #[inline]
-    fn drop_y(&self, board: &Board) -> i8 {
+    pub fn drop_y(&self, board: &Board) -> i8 {
         // This function is slow and can probably be improved with some bitboard magic
         let mut y = self.y;
 
@@ -98,17 +151,17 @@ impl Location {
     }
 
     #[inline]
-    fn cells(&self) -> [(i8, i8); 4] {
+    pub fn cells(&self) -> [(i8, i8); 4] {
         let mut cells = self.piece.cells(&self.orientation);
-        for i in 0..4 {
-            cells[i].0 += self.x;
-            cells[i].1 += self.y;
+        for cell in &mut cells {
+            cell.0 += self.x;
+            cell.1 += self.y;
         }
         cells
     }
 
     #[inline]
-    fn shift(&self, board: &Board, offset: i8) -> Option<Move> {
+    pub fn shift(&self, board: &Board, offset: i8) -> Option<Move> {
         let location = Location {
             x: self.x + offset,
             ..*self
@@ -123,32 +176,25 @@ impl Location {
     }
 
     #[inline]
-    fn rotate(&self, board: &Board, rotation: Rotation) -> Option<Move> {
+    pub fn rotate(&self, board: &Board, rotation: Rotation) -> Option<Move> {
+        self.rotate_with_kick(board, rotation).map(|(mv, _)| mv)
+    }
+
+    // Same as rotate but also says which kick in the srs table got used
+    #[inline]
+    pub(crate) fn rotate_with_kick(
+        &self,
+        board: &Board,
+        rotation: Rotation,
+    ) -> Option<(Move, usize)> {
         let srs_table = self.piece.srs_table(&self.orientation, rotation);
 
-        let orientation = match self.orientation {
-            Orientation::North => match rotation {
-                Rotation::Clockwise => Orientation::East,
-                Rotation::AntiClockwise => Orientation::West,
-            },
-            Orientation::East => match rotation {
-                Rotation::Clockwise => Orientation::South,
-                Rotation::AntiClockwise => Orientation::North,
-            },
-            Orientation::South => match rotation {
-                Rotation::Clockwise => Orientation::West,
-                Rotation::AntiClockwise => Orientation::East,
-            },
-            Orientation::West => match rotation {
-                Rotation::Clockwise => Orientation::North,
-                Rotation::AntiClockwise => Orientation::South,
-            },
-        };
+        let orientation = self.orientation.rotate(rotation);
 
-        for i in 0..5 {
+        for (i, (kick_x, kick_y)) in srs_table.into_iter().enumerate() {
             let location = Location {
-                x: self.x + srs_table[i].0,
-                y: self.y + srs_table[i].1,
+                x: self.x + kick_x,
+                y: self.y + kick_y,
                 orientation,
                 ..*self
             };
@@ -176,14 +222,14 @@ impl Location {
                         }
                     }
                 }
-                return Some(Move { location, spin });
+                return Some((Move { location, spin }, i));
             }
         }
         None
     }
 
     #[inline]
-    fn soft_drop(&self, board: &Board) -> Option<Move> {
+    pub fn soft_drop(&self, board: &Board) -> Option<Move> {
         let y = self.drop_y(board);
         if y == self.y {
             return None;
@@ -196,25 +242,68 @@ impl Location {
 }
 
 impl Board {
+    // An empty board with nothing in hold, no combo and `queue` still to come
+    pub fn new(queue: Vec<Piece>) -> Self {
+        Board {
+            hold: None,
+            can_hold: true,
+            queue,
+            queue_cursor: 0,
+            combo: 0,
+            back_to_back: false,
+            board: [[None; 10]; 40],
+        }
+    }
+
+    // Fill in a block of cells with garbage, mostly for setting up positions
+    pub fn fill(&mut self, rows: Range<usize>, columns: Range<usize>) {
+        for row in &mut self.board[rows] {
+            for cell in &mut row[columns.clone()] {
+                *cell = Some(Piece::G.to_char());
+            }
+        }
+    }
+
     pub fn from_tbp(tbp_board: TBPBoard) -> Self {
         let mut board = [[None; 10]; 40];
-        for i in 0..40 {
-            for j in 0..10 {
-                board[i][j] = tbp_board.board[i][j];
+        for (row, tbp_row) in board.iter_mut().zip(&tbp_board.board) {
+            for (cell, tbp_cell) in row.iter_mut().zip(tbp_row) {
+                *cell = *tbp_cell;
             }
         }
         Board {
             hold: tbp_board.hold,
+            can_hold: true,
             queue: tbp_board.queue,
+            queue_cursor: 0,
             combo: tbp_board.combo,
             back_to_back: tbp_board.back_to_back,
             board,
         }
     }
 
+    pub fn to_tbp(&self) -> TBPBoard {
+        TBPBoard {
+            hold: self.hold,
+            queue: self.next_pieces().to_vec(),
+            combo: self.combo,
+            back_to_back: self.back_to_back,
+            board: self.board.iter().map(|row| row.to_vec()).collect(),
+            randomizer: None,
+        }
+    }
+
+    // The pieces that haven't been played yet, starting with the current piece
+    #[inline]
+    pub fn next_pieces(&self) -> &[Piece] {
+        &self.queue[self.queue_cursor..]
+    }
+
     #[inline]
     pub fn occupied(&self, x: i8, y: i8) -> bool {
-        !(0..10).contains(&x) || !(0..40).contains(&y) || self.board[y as usize][x as usize] != None
+        !(0..10).contains(&x)
+            || !(0..40).contains(&y)
+            || self.board[y as usize][x as usize].is_some()
     }
 
     #[inline]
@@ -225,21 +314,38 @@ impl Board {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     pub fn gen_moves(&self) -> Vec<Move> {
         let mut move_list = Vec::new();
-        move_list.append(&mut self.gen_moves_for_piece(self.queue[0]));
+        let queue = self.next_pieces();
+        // Nothing to place if we've run out of pieces, search past the queue with
+        // Randomizer::continuations
+        if queue.is_empty() {
+            return move_list;
+        }
+        move_list.append(&mut self.gen_moves_for_piece(queue[0]));
+        if !self.can_hold {
+            return move_list;
+        }
         if let Some(hold) = self.hold {
             move_list.append(&mut self.gen_moves_for_piece(hold));
-        } else if self.queue.len() >= 2 {
-            move_list.append(&mut self.gen_moves_for_piece(self.queue[1]));
+        } else if queue.len() >= 2 {
+            move_list.append(&mut self.gen_moves_for_piece(queue[1]));
         }
 
         move_list
     }
 
+    // The move from gen_moves that puts the piece where `mv` does. Only the placement has to
+    // match, the spin always comes from gen_moves.
+    pub fn legal_move(&self, mv: Move) -> Option<Move> {
+        self.gen_moves()
+            .into_iter()
+            .find(|legal| legal.location == mv.location)
+    }
+
     pub fn gen_moves_for_piece(&self, piece: Piece) -> Vec<Move> {
         // Performance:
         // Hash set functions account for like 40% of this function REDUCED to like %30 with fxhash
@@ -254,12 +360,7 @@ impl Board {
         let mut queue = VecDeque::new();
         let mut hash = FxHashSet::default();
 
-        let initial_location = Location {
-            piece,
-            orientation: Orientation::North,
-            x: 5,
-            y: 19,
-        };
+        let initial_location = Location::spawn(piece);
 
         let initial_move = Move {
             location: initial_location,
@@ -324,80 +425,129 @@ impl Board {
     pub fn make_move(&self, mv: Move) -> Board {
         // I hope making a new board isnt that bad
         // There is like almost a 100% chance this isnt optimal
-        // It is very bad.
+        // Searches should use apply and undo instead which don't allocate
+        let mut new_board = self.clone();
+        new_board.make_move_in_place(mv);
+        new_board
+    }
+
+    pub fn make_move_in_place(&mut self, mv: Move) {
+        self.apply(mv);
+        self.forget_played_pieces();
+    }
+
+    // Once nothing is going to undo back past here the used up pieces can go
+    pub fn forget_played_pieces(&mut self) {
+        self.queue.drain(..self.queue_cursor);
+        self.queue_cursor = 0;
+    }
+
+    pub fn apply(&mut self, mv: Move) -> Undo {
         // Using column major bitboards means the pext trick shown in cc2 can be used which is way
         // faster than this stuff
         // Using row major bitboards might be faster because you would only have to do 1 or 2 pext
         // instructions and then a bunch of shifts but the shifts might be optimized with simd
-        let queue = self.queue.clone();
-        let mut new_board = Board { queue, ..*self };
-        let piece = mv.location.cells();
-        let mut cleared_lines = Vec::new();
-        for (x, y) in piece {
-            new_board.board[y as usize][x as usize] = Some('T');
-
-            if cleared_lines.contains(&y) {
-                continue;
-            }
+        let mut undo = Undo {
+            mv,
+            lines_cleared: 0,
+            cleared_rows: [(0, [None; 10]); 4],
+            hold: self.hold,
+            queue_cursor: self.queue_cursor,
+            combo: self.combo,
+            back_to_back: self.back_to_back,
+        };
 
-            let mut did_clear = true;
-            for x in 0..10 {
-                if new_board.board[y as usize][x as usize] == None {
-                    did_clear = false;
-                    break;
-                }
-            }
-            if did_clear {
-                cleared_lines.push(y);
+        let cells = mv.location.cells();
+        for (x, y) in cells {
+            self.board[y as usize][x as usize] = Some(mv.location.piece.to_char());
+        }
+
+        // The only rows that can be cleared are the ones the piece is in
+        let mut rows = cells.map(|(_, y)| y as usize);
+        rows.sort_unstable();
+        for (i, &y) in rows.iter().enumerate() {
+            if (i > 0 && rows[i - 1] == y) || self.board[y].iter().any(|cell| cell.is_none()) {
+                continue;
             }
+            undo.cleared_rows[undo.lines_cleared as usize] = (y as u8, self.board[y]);
+            undo.lines_cleared += 1;
         }
-        // Clear line lol this code is so bad
-        cleared_lines.sort();
-        cleared_lines.reverse();
-        for line in cleared_lines {
-            for y in line..39 {
-                for x in 0..10 {
-                    new_board.board[y as usize][x as usize] =
-                        new_board.board[(y + 1) as usize][x as usize];
+
+        if undo.lines_cleared > 0 {
+            // Shift everything above the lowest cleared row down over the cleared rows
+            let cleared = &undo.cleared_rows[..undo.lines_cleared as usize];
+            let lowest = cleared[0].0 as usize;
+            let mut write = lowest;
+            for read in lowest..40 {
+                if cleared.iter().any(|&(y, _)| y as usize == read) {
+                    continue;
                 }
+                self.board[write] = self.board[read];
+                write += 1;
             }
-            for x in 0..10 {
-                new_board.board[39][x as usize] = None;
+            for row in &mut self.board[write..] {
+                *row = [None; 10];
             }
+
+            self.combo += 1;
+            self.back_to_back = undo.lines_cleared == 4 || mv.spin != Spin::None;
+        } else {
+            self.combo = 0;
         }
 
         // Update the queue
-        if mv.location.piece != new_board.queue[0] {
-            if new_board.hold == None && new_board.queue.len() >= 2 {
-                new_board.hold = Some(new_board.queue[0]);
-                new_board.queue.remove(0);
-            } else {
-                new_board.hold = Some(new_board.queue[0]);
+        let current = self.queue[self.queue_cursor];
+        if mv.location.piece != current {
+            if self.hold.is_none() && self.queue.len() - self.queue_cursor >= 2 {
+                // Holding with nothing in hold uses up the next piece too
+                self.queue_cursor += 1;
             }
+            self.hold = Some(current);
         }
-        new_board.queue.remove(0);
+        self.queue_cursor += 1;
 
-        new_board
+        undo
     }
 
-    pub fn make_move_in_place(&mut self, mv: Move) {
-        let board = self.make_move(mv);
-        *self = board;
+    pub fn undo(&mut self, undo: Undo) {
+        self.hold = undo.hold;
+        self.queue_cursor = undo.queue_cursor;
+        self.combo = undo.combo;
+        self.back_to_back = undo.back_to_back;
+
+        // Put the cleared rows back in, lowest first so the higher ones end up in the right place
+        for &(y, row) in &undo.cleared_rows[..undo.lines_cleared as usize] {
+            let y = y as usize;
+            self.board.copy_within(y..39, y + 1);
+            self.board[y] = row;
+        }
+
+        for (x, y) in undo.mv.location.cells() {
+            self.board[y as usize][x as usize] = None;
+        }
     }
 
+    #[deprecated(note = "use Board::render, which can draw somewhere other than stdout")]
     pub fn print(&self) {
-        for i in (0..40).rev() {
-            let mut str = String::new();
-            for j in 0..10 {
-                let char = self.board[i][j].unwrap_or('.');
-                str.insert(j, char);
-            }
-            println!("{}", str);
-        }
+        // Nothing to be done if stdout is gone
+        let _ = self.render(&mut std::io::stdout(), None);
     }
 }
 
 impl Piece {
+    pub fn to_char(&self) -> char {
+        match self {
+            Piece::O => 'O',
+            Piece::I => 'I',
+            Piece::T => 'T',
+            Piece::L => 'L',
+            Piece::J => 'J',
+            Piece::S => 'S',
+            Piece::Z => 'Z',
+            Piece::G => 'G',
+        }
+    }
+
     #[inline]
     pub fn cells(&self, orientation: &Orientation) -> [(i8, i8); 4] {
         // What a lovely looking function!
@@ -515,82 +665,56 @@ impl Piece {
 mod tests {
     #[test]
     fn line_clear() {
-        // What a lovely looking test!
-        let board = crate::Board {
-            back_to_back: false,
-            board: [
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [None; 10],
-                [
-                    Some('G'),
-                    Some('G'),
-                    Some('G'),
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                ],
-                [None; 10],
-            ],
-            combo: 0,
-            hold: None,
-            queue: vec![
-                crate::Piece::I,
-                crate::Piece::J,
-                crate::Piece::O,
-                crate::Piece::S,
-                crate::Piece::Z,
-                crate::Piece::L,
-                crate::Piece::T,
-            ],
-        };
-        let mut moves = board.gen_moves(crate::Piece::T);
+        let mut board = crate::Board::new(vec![
+            crate::Piece::I,
+            crate::Piece::J,
+            crate::Piece::O,
+            crate::Piece::S,
+            crate::Piece::Z,
+            crate::Piece::L,
+            crate::Piece::T,
+        ]);
+        board.fill(38..39, 0..3);
+        let mut moves = board.gen_moves_for_piece(crate::Piece::T);
         moves.sort();
         moves.dedup();
         println!("{:?}", moves);
         let new_board = board.make_move(moves[12]);
-        board.print();
-        new_board.print();
-        assert_eq!(false, board.collision(&moves[0].location));
-        assert_eq!(33, moves.len());
+        board.render(&mut std::io::stderr(), None).unwrap();
+        new_board.render(&mut std::io::stderr(), None).unwrap();
+        assert!(!board.collision(&moves[0].location));
+        // The garbage is up at row 38 so this is really just an empty board
+        assert_eq!(34, moves.len());
+    }
+
+    #[test]
+    fn apply_undo() {
+        use crate::*;
+
+        let mut board = Board::new(vec![Piece::J, Piece::I, Piece::T]);
+        board.fill(0..2, 4..10);
+        board.board[2][9] = Some('G');
+        let original = board.clone();
+
+        // Hold the J and put the I in the bottom row
+        let mv = Move {
+            location: Location {
+                piece: Piece::I,
+                orientation: Orientation::North,
+                x: 1,
+                y: 0,
+            },
+            spin: Spin::None,
+        };
+        let undo = board.apply(mv);
+        assert_eq!(1, undo.lines_cleared);
+        assert_eq!(Some('G'), board.board[1][9]);
+        assert_eq!(None, board.board[2][9]);
+        assert_eq!(Some(Piece::J), board.hold);
+        assert_eq!(&[Piece::T], board.next_pieces());
+        assert_eq!(1, board.combo);
+
+        board.undo(undo);
+        assert_eq!(original, board);
     }
 }
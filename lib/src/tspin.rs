@@ -0,0 +1,267 @@
+use crate::tetris::*;
+use rustc_hash::FxHashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TSpinKind {
+    Mini,
+    Single,
+    Double,
+    Triple,
+}
+
+// How the T gets into the slot, which doesn't depend on how many lines it clears
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TSpinShape {
+    Standard,
+    // Needs the last kick to get into, with the T ending up sideways
+    Fin,
+    // Needs the (0, -2) style kick
+    Neo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TSpinSlot {
+    pub kind: TSpinKind,
+    pub shape: TSpinShape,
+    // The T placement that fills the slot
+    pub mv: Move,
+    pub lines: u8,
+    // Whether a T from spawn can actually get there right now
+    pub fillable: bool,
+}
+
+impl Board {
+    // Finds every spot where a T would lock as a spin and clear lines. A slot counts if some
+    // rotation into it gets a spin from Location::rotate, so it uses exactly the same corner and
+    // kick rules as move gen.
+    pub fn tspin_slots(&self) -> Vec<TSpinSlot> {
+        let reachable: FxHashSet<Move> = self.gen_moves_for_piece(Piece::T).into_iter().collect();
+        let top = (0..40)
+            .rev()
+            .find(|&y| self.board[y].iter().any(|cell| cell.is_some()))
+            .map_or(0, |y| y as i8 + 1);
+
+        let mut slots = Vec::new();
+        for orientation in [
+            Orientation::North,
+            Orientation::East,
+            Orientation::South,
+            Orientation::West,
+        ] {
+            for y in 0..=top.min(38) {
+                for x in 0..10 {
+                    let location = Location {
+                        piece: Piece::T,
+                        orientation,
+                        x,
+                        y,
+                    };
+                    if self.collision(&location)
+                        || !self.collision(&Location {
+                            y: y - 1,
+                            ..location
+                        })
+                    {
+                        continue;
+                    }
+                    if let Some(slot) = self.tspin_slot(location, &reachable) {
+                        slots.push(slot);
+                    }
+                }
+            }
+        }
+        slots
+    }
+
+    fn tspin_slot(&self, location: Location, reachable: &FxHashSet<Move>) -> Option<TSpinSlot> {
+        // Find the best spin we can get by rotating into this location from somewhere else
+        let mut best: Option<(Spin, usize)> = None;
+        for rotation in [Rotation::Clockwise, Rotation::AntiClockwise] {
+            let from = location.orientation.rotate(match rotation {
+                Rotation::Clockwise => Rotation::AntiClockwise,
+                Rotation::AntiClockwise => Rotation::Clockwise,
+            });
+            let kicks = Piece::T.srs_table(&from, rotation);
+            for (x, y) in kicks {
+                let start = Location {
+                    orientation: from,
+                    x: location.x - x,
+                    y: location.y - y,
+                    ..location
+                };
+                if self.collision(&start) {
+                    continue;
+                }
+                if let Some((mv, kick)) = start.rotate_with_kick(self, rotation) {
+                    if mv.location == location
+                        && mv.spin != Spin::None
+                        && best.is_none_or(|(spin, _)| mv.spin > spin)
+                    {
+                        best = Some((mv.spin, kick));
+                    }
+                }
+            }
+        }
+        let (spin, kick) = best?;
+
+        let cells = location.cells();
+        let mut rows: Vec<i8> = cells.iter().map(|&(_, y)| y).collect();
+        rows.sort_unstable();
+        rows.dedup();
+        let lines = rows
+            .into_iter()
+            .filter(|&y| (0..10).all(|x| self.occupied(x, y) || cells.contains(&(x, y))))
+            .count() as u8;
+        if lines == 0 {
+            return None;
+        }
+
+        let kind = match (spin, lines) {
+            (Spin::Mini, _) => TSpinKind::Mini,
+            (_, 1) => TSpinKind::Single,
+            (_, 2) => TSpinKind::Double,
+            _ => TSpinKind::Triple,
+        };
+        let sideways = matches!(location.orientation, Orientation::East | Orientation::West);
+        let shape = match kick {
+            4 if sideways => TSpinShape::Fin,
+            3 => TSpinShape::Neo,
+            _ => TSpinShape::Standard,
+        };
+
+        let mv = Move { location, spin };
+        Some(TSpinSlot {
+            kind,
+            shape,
+            mv,
+            lines,
+            fillable: reachable.contains(&mv),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn tsd_slot() {
+        let mut board = Board::new(vec![]);
+        // A plain tsd slot in columns 3-5
+        board.fill(0..1, 0..4);
+        board.fill(0..1, 5..10);
+        board.fill(1..2, 0..3);
+        board.fill(1..2, 6..10);
+        board.board[2][3] = Some('G');
+
+        let slots = board.tspin_slots();
+        let tsd = slots
+            .iter()
+            .find(|slot| slot.kind == TSpinKind::Double)
+            .unwrap();
+        assert_eq!(TSpinShape::Standard, tsd.shape);
+        assert_eq!(2, tsd.lines);
+        assert_eq!(Orientation::South, tsd.mv.location.orientation);
+        assert_eq!((4, 1), (tsd.mv.location.x, tsd.mv.location.y));
+        assert!(tsd.fillable);
+    }
+
+    // Rows from the top down, ending with row 0
+    fn board_from(rows: &[&str]) -> Board {
+        let mut board = Board::new(vec![]);
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                if cell == '#' {
+                    board.board[y][x] = Some('G');
+                }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn slot_kinds() {
+        let fin = [
+            ".##...##..",
+            "#..##.#...",
+            "#######.##",
+            "#######..#",
+            "###.###.##",
+        ];
+        // The same fin with nothing in the way of the bottom line
+        let mut fin_triple = fin;
+        fin_triple[4] = "#######.##";
+        let cases = [
+            (
+                (TSpinKind::Mini, TSpinShape::Standard),
+                1,
+                (Orientation::West, 9, 2),
+                vec!["#########.", "###.#.####"],
+            ),
+            (
+                (TSpinKind::Single, TSpinShape::Standard),
+                1,
+                (Orientation::East, 4, 3),
+                vec![
+                    ".....##...",
+                    "..#....#..",
+                    "####.#####",
+                    ".#.#######",
+                    "###.######",
+                ],
+            ),
+            // The (0, -2) kick
+            (
+                (TSpinKind::Triple, TSpinShape::Neo),
+                3,
+                (Orientation::West, 4, 1),
+                vec![
+                    "....#.....",
+                    "..........",
+                    "####.#####",
+                    "###..#####",
+                    "####.#####",
+                ],
+            ),
+            (
+                (TSpinKind::Double, TSpinShape::Neo),
+                2,
+                (Orientation::West, 5, 1),
+                vec![
+                    ".....#....",
+                    "..........",
+                    "#####.####",
+                    "####..##.#",
+                    "#####.####",
+                ],
+            ),
+            // The last kick, ending sideways
+            (
+                (TSpinKind::Double, TSpinShape::Fin),
+                2,
+                (Orientation::East, 7, 1),
+                fin.to_vec(),
+            ),
+            (
+                (TSpinKind::Triple, TSpinShape::Fin),
+                3,
+                (Orientation::East, 7, 1),
+                fin_triple.to_vec(),
+            ),
+        ];
+        for ((kind, shape), lines, (orientation, x, y), rows) in cases {
+            let slots = board_from(&rows).tspin_slots();
+            let slot = slots
+                .iter()
+                .find(|slot| (slot.kind, slot.shape) == (kind, shape))
+                .unwrap_or_else(|| panic!("no {:?} {:?} slot in {:?}", shape, kind, slots));
+            assert_eq!(lines, slot.lines, "{:?} {:?}", shape, kind);
+            let location = slot.mv.location;
+            assert_eq!(
+                (orientation, x, y),
+                (location.orientation, location.x, location.y)
+            );
+            assert!(slot.fillable, "{:?} {:?}", shape, kind);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::Mcts;
+use lib::*;
+
+pub const BOT_INFO: BotInfo = BotInfo {
+    name: "MCTS Bot",
+    author: "bpaul",
+    version: "v1",
+    features: &["randomizer"],
+};
+
+#[derive(Clone)]
+pub struct Settings {
+    pub exploration: f32,
+    pub depth: usize,
+    pub evaluator: LinearEvaluator,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            exploration: 1.4,
+            depth: 8,
+            evaluator: LinearEvaluator::default(),
+        }
+    }
+}
+
+// How many iterations to run between checking for messages from the frontend
+const BATCH: usize = 32;
+
+pub struct MctsBot {
+    board: Board,
+    settings: Settings,
+}
+
+impl Bot for MctsBot {
+    type Settings = Settings;
+
+    fn new(board: Board, _rules: &GameRules, settings: Settings) -> Self {
+        MctsBot { board, settings }
+    }
+    fn search(&self, search_status: &SearchStatus) {
+        let settings = &self.settings;
+        let mut mcts = Mcts::new(
+            self.board.clone(),
+            search_status.randomizer(),
+            settings.evaluator.clone(),
+            rand::random(),
+        );
+        mcts.exploration = settings.exploration;
+        mcts.depth = settings.depth;
+
+        let mut iterations = 0;
+        loop {
+            for _ in 0..BATCH {
+                mcts.iterate();
+            }
+            iterations += BATCH as u64;
+            let mut info = MoveInfo::with_nodes(iterations, search_status.elapsed());
+            info.extra
+                .insert("tree_size".to_string(), mcts.node_count().into());
+            search_status.current_moves_with_info(&mcts.best_moves(), &info);
+            if search_status.terminate() {
+                break;
+            }
+            // Only up to a suggest, so it gets answered for the position it was asked about
+            while let Some(event) = search_status.next_event() {
+                match event {
+                    SearchEvent::Play(mv) => mcts.advance(mv),
+                    SearchEvent::NewPiece(piece) => mcts.add_piece(piece),
+                    SearchEvent::Suggest => break,
+                    SearchEvent::Stop => return,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Open loop Monte Carlo tree search over `Board::gen_moves`, and the bot that runs it. Both are
+//! kept out of the bot binary so they can be driven without a frontend, or hosted in process with
+//! `TbpClient::from_bot`.
+
+use lib::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+
+mod bot;
+
+pub use crate::bot::*;
+
+struct Node {
+    mv: Option<Move>,
+    children: Vec<usize>,
+    visits: u32,
+    total: f32,
+}
+
+pub struct Mcts<E: Evaluator> {
+    pub exploration: f32,
+    // How many placements deep an iteration goes before it just uses the evaluator
+    pub depth: usize,
+    // Stop growing the tree past this many nodes so it doesn't eat all the memory
+    pub max_nodes: usize,
+    evaluator: E,
+    board: Board,
+    randomizer: Randomizer,
+    nodes: Vec<Node>,
+    rng: StdRng,
+    // The range of values seen so far, used to squash them into 0..1 for ucb
+    min_value: f32,
+    max_value: f32,
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn new(board: Board, randomizer: Randomizer, evaluator: E, seed: u64) -> Self {
+        Mcts {
+            exploration: 1.4,
+            depth: 8,
+            max_nodes: 2_000_000,
+            evaluator,
+            board,
+            randomizer,
+            nodes: vec![Node::new(None)],
+            rng: StdRng::seed_from_u64(seed),
+            min_value: f32::INFINITY,
+            max_value: f32::NEG_INFINITY,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn root_visits(&self) -> u32 {
+        self.nodes[0].visits
+    }
+
+    // One selection, expansion, evaluation and backpropagation
+    pub fn iterate(&mut self) {
+        let mut board = self.board.clone();
+        let mut randomizer = self.randomizer.clone();
+        let mut path = vec![0];
+        let mut reward = 0.0;
+        let mut value = 0.0;
+
+        while path.len() <= self.depth {
+            if board.next_pieces().is_empty() {
+                // Chance node, make up the next piece
+                let outcomes = randomizer.outcomes();
+                let piece = outcomes[self.rng.gen_range(0..outcomes.len())].piece;
+                randomizer.take(piece);
+                board.queue.push(piece);
+            }
+            if board.blocked_out() {
+                value = TOPPED_OUT;
+                break;
+            }
+            let moves = board.gen_moves();
+
+            let node = path[path.len() - 1];
+            let unexpanded: Vec<Move> = moves
+                .iter()
+                .copied()
+                .filter(|&mv| !self.has_child(node, mv))
+                .collect();
+            let expanded = !unexpanded.is_empty() && self.nodes.len() < self.max_nodes;
+            let child = if expanded {
+                let mv = unexpanded[self.rng.gen_range(0..unexpanded.len())];
+                self.nodes.push(Node::new(Some(mv)));
+                let child = self.nodes.len() - 1;
+                self.nodes[node].children.push(child);
+                child
+            } else {
+                match self.select(node, &moves) {
+                    Some(child) => child,
+                    None => break,
+                }
+            };
+
+            let mv = self.nodes[child].mv.unwrap();
+            let undo = board.apply(mv);
+            let placement = Placement::new(&board, &undo);
+            let evaluation = self.evaluator.evaluate(&board, &placement);
+            reward += evaluation.reward;
+            value = evaluation.value;
+            path.push(child);
+            if mv.locks_out() {
+                value = TOPPED_OUT;
+                break;
+            }
+            if expanded {
+                break;
+            }
+        }
+
+        let total = reward + value;
+        self.min_value = self.min_value.min(total);
+        self.max_value = self.max_value.max(total);
+        for node in path {
+            self.nodes[node].visits += 1;
+            self.nodes[node].total += total;
+        }
+    }
+
+    fn has_child(&self, node: usize, mv: Move) -> bool {
+        self.nodes[node]
+            .children
+            .iter()
+            .any(|&child| self.nodes[child].mv == Some(mv))
+    }
+
+    // The legal child with the best ucb score
+    fn select(&self, node: usize, moves: &[Move]) -> Option<usize> {
+        let range = (self.max_value - self.min_value).max(f32::EPSILON);
+        let parent_visits = (self.nodes[node].visits.max(1) as f32).ln();
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .filter(|&child| moves.contains(&self.nodes[child].mv.unwrap()))
+            .map(|child| {
+                let child_node = &self.nodes[child];
+                let visits = child_node.visits.max(1) as f32;
+                let mean = (child_node.total / visits - self.min_value) / range;
+                let score = mean + self.exploration * (parent_visits / visits).sqrt();
+                (child, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(child, _)| child)
+    }
+
+    // The moves from the current position, most visited first
+    pub fn best_moves(&self) -> Vec<Move> {
+        let legal = self.board.gen_moves();
+        let mut children: Vec<&Node> = self.nodes[0]
+            .children
+            .iter()
+            .map(|&child| &self.nodes[child])
+            .filter(|child| legal.contains(&child.mv.unwrap()))
+            .collect();
+        children.sort_by_key(|child| Reverse(child.visits));
+        children.iter().map(|child| child.mv.unwrap()).collect()
+    }
+
+    // Play a move, keeping the part of the tree under it
+    pub fn advance(&mut self, mv: Move) {
+        self.board.make_move_in_place(mv);
+        let old = std::mem::take(&mut self.nodes);
+        let root = old[0]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| old[child].mv == Some(mv));
+
+        self.nodes = vec![];
+        // Values from the rest of the old tree are for positions that can't happen any more. The
+        // means in the kept part are the best guess at the range without them.
+        self.min_value = f32::INFINITY;
+        self.max_value = f32::NEG_INFINITY;
+        let Some(root) = root else {
+            self.nodes.push(Node::new(None));
+            return;
+        };
+        let mut stack: Vec<(usize, Option<usize>)> = vec![(root, None)];
+        while let Some((index, parent)) = stack.pop() {
+            let new_index = self.nodes.len();
+            self.nodes.push(Node {
+                mv: old[index].mv,
+                children: Vec::with_capacity(old[index].children.len()),
+                visits: old[index].visits,
+                total: old[index].total,
+            });
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(new_index);
+            }
+            if old[index].visits > 0 {
+                let mean = old[index].total / old[index].visits as f32;
+                self.min_value = self.min_value.min(mean);
+                self.max_value = self.max_value.max(mean);
+            }
+            for &child in &old[index].children {
+                stack.push((child, Some(new_index)));
+            }
+        }
+    }
+
+    pub fn add_piece(&mut self, piece: Piece) {
+        self.board.queue.push(piece);
+        self.randomizer.take(piece);
+    }
+}
+
+impl Node {
+    fn new(mv: Option<Move>) -> Self {
+        Node {
+            mv,
+            children: Vec::new(),
+            visits: 0,
+            total: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn advance_keeps_the_subtree() {
+        let board = Board::new(vec![Piece::T, Piece::I, Piece::O]);
+        let mut mcts = Mcts::new(board, Randomizer::default(), LinearEvaluator::default(), 1);
+        for _ in 0..300 {
+            mcts.iterate();
+        }
+        let best = mcts.best_moves()[0];
+        let child = mcts.nodes[0]
+            .children
+            .iter()
+            .map(|&child| &mcts.nodes[child])
+            .find(|child| child.mv == Some(best))
+            .unwrap();
+        let (visits, grandchildren) = (child.visits, child.children.len());
+        assert!(grandchildren > 0);
+        let (min_value, max_value) = (mcts.min_value, mcts.max_value);
+        let mut expected = mcts.board().clone();
+        expected.make_move_in_place(best);
+
+        mcts.advance(best);
+        assert_eq!(visits, mcts.root_visits());
+        assert_eq!(grandchildren, mcts.nodes[0].children.len());
+        assert_eq!(&expected, mcts.board());
+        // The value range only covers what's left of the tree
+        let means = mcts
+            .nodes
+            .iter()
+            .filter(|node| node.visits > 0)
+            .map(|node| node.total / node.visits as f32);
+        let (min_mean, max_mean) = means
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), mean| {
+                (min.min(mean), max.max(mean))
+            });
+        assert_eq!((min_mean, max_mean), (mcts.min_value, mcts.max_value));
+        assert!(min_value <= mcts.min_value && mcts.max_value <= max_value);
+        assert!((min_value, max_value) != (mcts.min_value, mcts.max_value));
+        let legal = mcts.board().gen_moves();
+        assert!(mcts.best_moves().iter().all(|mv| legal.contains(mv)));
+
+        // Something that was never searched starts over
+        let other = *legal.iter().find(|&&mv| !mcts.has_child(0, mv)).unwrap();
+        mcts.advance(other);
+        assert_eq!((0, 1), (mcts.root_visits(), mcts.node_count()));
+        assert_eq!(f32::INFINITY, mcts.min_value);
+    }
+
+    #[test]
+    fn topping_out_is_worst() {
+        // Only the top two rows are free, so anything placed locks out or blocks the next spawn
+        let mut board = Board::new(vec![Piece::O, Piece::O]);
+        board.fill(0..38, 0..9);
+        let mut mcts = Mcts::new(board, Randomizer::default(), LinearEvaluator::default(), 1);
+        for _ in 0..50 {
+            mcts.iterate();
+        }
+        assert!(mcts.max_value <= TOPPED_OUT);
+    }
+}
@@ -0,0 +1,23 @@
+use bin_utils::parse_arg;
+use lib::*;
+use mcts_bot::*;
+use std::env;
+
+fn main() {
+    let mut settings = Settings::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exploration" => settings.exploration = parse_arg(args.next(), "exploration"),
+            "--depth" => settings.depth = parse_arg(args.next(), "depth"),
+            "--weights" => {
+                let path = args.next().expect("--weights needs a file");
+                settings.evaluator =
+                    LinearEvaluator::from_file(&path).expect("couldn't load weights")
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    run_bot::<MctsBot>(BOT_INFO, settings);
+}
@@ -0,0 +1,41 @@
+use bin_utils::parse_arg;
+use lib::*;
+use std::env;
+use std::time::Instant;
+
+// Usage: pc-pruning-table [output file] [height]
+// The height is 2 unless you ask for 4, which takes a long time: upwards of 15 minutes and several
+// hundred MB
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .unwrap_or_else(|| "pc_pruning_table.bin".to_string());
+    let height = args
+        .next()
+        .map_or(2, |height| parse_arg(Some(height), "height"));
+    if height > MAX_PC_HEIGHT {
+        eprintln!(
+            "Can't make a pruning table taller than {} lines",
+            MAX_PC_HEIGHT
+        );
+        return;
+    }
+    // 10 cells a line and 4 a piece
+    if !height.is_multiple_of(2) {
+        eprintln!("An empty board only has perfect clears in an even number of lines");
+        return;
+    }
+
+    let start = Instant::now();
+    let table = PcPruningTable::generate(height);
+    println!(
+        "Found {} fields that can be perfect cleared within {} lines in {:.1?}",
+        table.len(),
+        height,
+        start.elapsed()
+    );
+
+    table.save(&path).expect("couldn't write the pruning table");
+    println!("Saved to {}", path);
+}
@@ -6,42 +6,47 @@ struct RandomBot {
 }
 
 impl Bot for RandomBot {
-    fn new(board: Board) -> Self {
+    type Settings = ();
+
+    fn new(board: Board, _rules: &GameRules, _: ()) -> Self {
         RandomBot { board }
     }
     fn search(&self, search_status: &SearchStatus) {
-        let mutable_board = &mut self.board.clone();
-
-        let mut mv = self.get_move(mutable_board);
+        let mut generation = search_status.generation();
+        let mut mv = self.get_move(&self.board);
 
         loop {
-            search_status.current_moves(&vec![mv]);
-            if search_status.terminate() {
+            search_status.current_moves(mv.as_slice());
+            if !search_status.catch_up() {
                 break;
             }
-            if let Some(new_move) = search_status.new_move() {
-                mutable_board.make_move_in_place(new_move);
-                mv = self.get_move(mutable_board);
-            }
-            if let Some(piece) = search_status.new_piece() {
-                mutable_board.queue.push(piece);
+            if search_status.generation() != generation {
+                generation = search_status.generation();
+                mv = self.get_move(&search_status.board());
             }
         }
     }
 }
 
 impl RandomBot {
-    fn get_move(&self, board: &Board) -> Move {
+    // None when there's nothing to place, like when the queue has run out
+    fn get_move(&self, board: &Board) -> Option<Move> {
         let moves = board.gen_moves();
-        moves[rand::thread_rng().gen_range(0..moves.len())]
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[rand::thread_rng().gen_range(0..moves.len())])
     }
 }
 
 fn main() {
-    run_bot::<RandomBot>(BotInfo {
-        name: "Random Move Bot",
-        author: "bpaul",
-        version: "v1 (the only version)",
-        features: &[],
-    });
+    run_bot::<RandomBot>(
+        BotInfo {
+            name: "Random Move Bot",
+            author: "bpaul",
+            version: "v1 (the only version)",
+            features: &[],
+        },
+        (),
+    );
 }
@@ -0,0 +1,311 @@
+use lib::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::time::{Duration, Instant};
+
+const PREVIEW: usize = 5;
+// How long a piece can sit on the stack before it locks, and how many times moving it can start
+// that clock over so it can't be stalled forever
+const LOCK_DELAY: Duration = Duration::from_millis(500);
+const MAX_LOCK_RESETS: u32 = 15;
+
+pub struct Tetris {
+    // Only ever has placed pieces in it, the falling one gets drawn on top
+    pub board: Board,
+    pub randomizer: Randomizer,
+    rng: StdRng,
+    // Where the falling piece is, with the spin it would lock with if it stopped here
+    pub falling: Move,
+    last_fall: Instant,
+    lock_started: Option<Instant>,
+    lock_resets: u32,
+    pub pieces: u32,
+    pub lines: u32,
+    pub attack: u32,
+    // What the last line clear was, like "T-spin double"
+    pub last_clear: String,
+    pub over: Option<GameEnd>,
+}
+
+impl Tetris {
+    pub fn new(seed: u64) -> Self {
+        let mut game = Tetris {
+            board: Board::new(Vec::new()),
+            randomizer: Randomizer::default(),
+            rng: StdRng::seed_from_u64(seed),
+            falling: Move {
+                location: Location::spawn(Piece::I),
+                spin: Spin::None,
+            },
+            last_fall: Instant::now(),
+            lock_started: None,
+            lock_resets: 0,
+            pieces: 0,
+            lines: 0,
+            attack: 0,
+            last_clear: String::new(),
+            over: None,
+        };
+        let piece = game.next_piece();
+        game.spawn(piece);
+        game
+    }
+
+    // Tops up the queue so there's always the current piece and the preview
+    fn next_piece(&mut self) -> Piece {
+        while self.board.next_pieces().len() <= PREVIEW {
+            let piece = self.randomizer.next_piece(&mut self.rng);
+            self.board.queue.push(piece);
+        }
+        self.board.next_pieces()[0]
+    }
+
+    fn spawn(&mut self, piece: Piece) {
+        self.falling = Move {
+            location: Location::spawn(piece),
+            spin: Spin::None,
+        };
+        self.last_fall = Instant::now();
+        self.lock_started = None;
+        self.lock_resets = 0;
+        if self.board.collision(&self.falling.location) {
+            self.over = Some(GameEnd::BlockOut);
+        }
+    }
+
+    // Gets faster every 10 lines, starting at a row a second
+    fn gravity(&self) -> Duration {
+        let level = (self.lines / 10).min(19) as i32;
+        Duration::from_secs_f64((0.8 - level as f64 * 0.007).powi(level))
+    }
+
+    fn on_ground(&self) -> bool {
+        let below = Location {
+            y: self.falling.location.y - 1,
+            ..self.falling.location
+        };
+        self.board.collision(&below)
+    }
+
+    // Moving a piece that's on the stack starts the lock delay over, up to a point
+    fn moved(&mut self, mv: Option<Move>) {
+        let Some(mv) = mv.filter(|_| self.over.is_none()) else {
+            return;
+        };
+        self.falling = mv;
+        if self.lock_started.is_some() && self.lock_resets < MAX_LOCK_RESETS {
+            self.lock_started = Some(Instant::now());
+            self.lock_resets += 1;
+        }
+    }
+
+    pub fn shift(&mut self, offset: i8) {
+        self.moved(self.falling.location.shift(&self.board, offset));
+    }
+
+    pub fn rotate(&mut self, rotation: Rotation) {
+        self.moved(self.falling.location.rotate(&self.board, rotation));
+    }
+
+    // Down one row, this is what gravity does too
+    pub fn fall(&mut self) {
+        if self.over.is_some() || self.on_ground() {
+            return;
+        }
+        self.falling = Move {
+            location: Location {
+                y: self.falling.location.y - 1,
+                ..self.falling.location
+            },
+            spin: Spin::None,
+        };
+        self.last_fall = Instant::now();
+    }
+
+    pub fn hard_drop(&mut self) {
+        if self.over.is_some() {
+            return;
+        }
+        // No soft drop means it's already on the stack and keeps whatever spin it has
+        if let Some(mv) = self.falling.location.soft_drop(&self.board) {
+            self.falling = mv;
+        }
+        self.lock();
+    }
+
+    // Swap with the hold piece, or the next piece if there's nothing held. This goes straight into
+    // the board instead of leaving it to Board::apply to work out from the piece that gets placed,
+    // which can't tell a hold happened when the next piece is the same as the current one. The
+    // board can't hold again until this piece locks, so a bot looking at it won't suggest a hold.
+    pub fn hold(&mut self) {
+        if self.over.is_some() || !self.board.can_hold {
+            return;
+        }
+        let current = self.board.next_pieces()[0];
+        match self.board.hold.replace(current) {
+            Some(held) => self.board.queue[self.board.queue_cursor] = held,
+            None => self.board.queue_cursor += 1,
+        }
+        self.board.forget_played_pieces();
+        let piece = self.next_piece();
+        self.spawn(piece);
+        self.board.can_hold = false;
+    }
+
+    // The board with the falling piece drawn in and only what comes after it in the queue
+    pub fn shown_board(&self) -> Board {
+        let mut board = self.board.clone();
+        board.queue = self.board.next_pieces()[1..].to_vec();
+        board.queue_cursor = 0;
+        for (x, y) in self.falling.location.cells() {
+            board.board[y as usize][x as usize] = Some(self.falling.location.piece.to_char());
+        }
+        board
+    }
+
+    // Where a hard drop would put the piece
+    pub fn landing(&self) -> Move {
+        self.falling
+            .location
+            .soft_drop(&self.board)
+            .unwrap_or(self.falling)
+    }
+
+    fn lock(&mut self) {
+        let mv = self.falling;
+        if mv.locks_out() {
+            self.over = Some(GameEnd::LockOut);
+            return;
+        }
+        let undo = self.board.apply(mv);
+        let placement = Placement::new(&self.board, &undo);
+        self.board.forget_played_pieces();
+        self.pieces += 1;
+        self.lines += placement.lines_cleared as u32;
+        self.attack += placement.attack;
+        if placement.lines_cleared > 0 || mv.spin != Spin::None {
+            self.last_clear = describe(&placement);
+        }
+        let piece = self.next_piece();
+        self.spawn(piece);
+        self.board.can_hold = true;
+    }
+
+    // Call this often, it does gravity and locking
+    pub fn tick(&mut self) {
+        if self.over.is_some() {
+            return;
+        }
+        if self.on_ground() {
+            let started = *self.lock_started.get_or_insert_with(Instant::now);
+            if started.elapsed() >= LOCK_DELAY {
+                self.lock();
+            }
+        } else {
+            self.lock_started = None;
+            if self.last_fall.elapsed() >= self.gravity() {
+                self.fall();
+            }
+        }
+    }
+}
+
+fn describe(placement: &Placement) -> String {
+    let mut parts = Vec::new();
+    if placement.back_to_back {
+        parts.push("B2B".to_string());
+    }
+    match placement.mv.spin {
+        Spin::Full => parts.push("T-spin".to_string()),
+        Spin::Mini => parts.push("T-spin mini".to_string()),
+        Spin::None => {}
+    }
+    let lines = ["", "single", "double", "triple", "tetris"][placement.lines_cleared as usize];
+    if !lines.is_empty() {
+        parts.push(lines.to_string());
+    }
+    if placement.combo > 1 {
+        parts.push(format!("{} combo", placement.combo - 1));
+    }
+    if placement.perfect_clear {
+        parts.push("perfect clear!".to_string());
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_queue(queue: &[Piece]) -> Tetris {
+        let mut game = Tetris::new(1);
+        game.board.queue = queue.to_vec();
+        game.board.queue_cursor = 0;
+        game.spawn(queue[0]);
+        game
+    }
+
+    #[test]
+    fn hold_the_same_piece() {
+        let mut game = with_queue(&[Piece::T, Piece::T, Piece::I, Piece::O, Piece::S, Piece::Z]);
+        game.hold();
+        assert_eq!(Some(Piece::T), game.board.hold);
+        assert_eq!(Piece::T, game.falling.location.piece);
+        assert_eq!(Piece::I, game.shown_board().queue[0]);
+        // Once per piece
+        assert!(!game.board.can_hold);
+        game.hold();
+        assert_eq!(&[Piece::T, Piece::I], &game.board.next_pieces()[..2]);
+
+        game.hard_drop();
+        assert!(game.board.can_hold);
+        assert_eq!(Some(Piece::T), game.board.hold);
+        assert_eq!(Piece::I, game.falling.location.piece);
+        game.hold();
+        assert_eq!(Some(Piece::I), game.board.hold);
+        assert_eq!(Piece::T, game.falling.location.piece);
+        assert_eq!(Piece::O, game.board.next_pieces()[1]);
+    }
+
+    #[test]
+    fn lock_delay() {
+        let mut game = with_queue(&[Piece::O, Piece::I, Piece::T, Piece::S, Piece::Z, Piece::J]);
+        game.falling = game.landing();
+        game.tick();
+        assert_eq!(0, game.pieces);
+
+        // Moving on the stack starts the clock over
+        game.lock_started = Some(Instant::now() - LOCK_DELAY);
+        game.shift(1);
+        game.tick();
+        assert_eq!(0, game.pieces);
+
+        // Until it's been done too many times
+        game.lock_resets = MAX_LOCK_RESETS;
+        game.lock_started = Some(Instant::now() - LOCK_DELAY);
+        game.shift(-1);
+        game.tick();
+        assert_eq!(1, game.pieces);
+        assert_eq!(Piece::I, game.falling.location.piece);
+    }
+
+    #[test]
+    fn wall_kick() {
+        let mut game = with_queue(&[Piece::I, Piece::O, Piece::T, Piece::S, Piece::Z, Piece::J]);
+        game.rotate(Rotation::Clockwise);
+        for _ in 0..10 {
+            game.shift(-1);
+        }
+        let before = game.falling.location;
+        assert!(game.board.collision(&Location {
+            orientation: Orientation::South,
+            ..before
+        }));
+
+        // Flat against the wall it only fits by getting kicked out
+        game.rotate(Rotation::Clockwise);
+        assert_eq!(Orientation::South, game.falling.location.orientation);
+        assert_ne!(before.x, game.falling.location.x);
+        assert!(!game.board.collision(&game.falling.location));
+    }
+}
@@ -0,0 +1,249 @@
+use bin_utils::parse_arg;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use lib::*;
+use std::env;
+use std::io::{self, Write};
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+mod game;
+
+use crate::game::*;
+
+// Which game (by seed) and which piece in it a hint is for, and whether that piece has been held
+type Position = (u64, u32, bool);
+
+const HELP: &str = "left/right move  down soft drop  space hard drop  up/x/z rotate  c hold  \
+                    h hint  r restart  q quit";
+
+// Usage: terminal-tetris [--seed <seed>] [--bot beam] [--think 100]
+// The bot is only there for hints, see hint_player for what it can be
+fn main() -> io::Result<()> {
+    let mut seed = None;
+    let mut bot = cfg!(feature = "bots").then(|| "beam".to_string());
+    let mut think_time = Duration::from_millis(100);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = Some(parse_arg(args.next(), "seed")),
+            "--bot" => bot = Some(args.next().expect("--bot needs a bot")),
+            "--think" => think_time = Duration::from_millis(parse_arg(args.next(), "think time")),
+            _ => {
+                eprintln!("Usage: terminal-tetris [--seed <seed>] [--bot beam] [--think 100]");
+                return Ok(());
+            }
+        }
+    }
+    let Some(bot) = bot else {
+        eprintln!("Built without the bots feature, so --bot has to be a command for a tbp bot");
+        return Ok(());
+    };
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs())
+    });
+    let hinter = Hinter::start(bot, seed, think_time).expect("couldn't start the bot");
+
+    let mut out = io::stdout();
+    // A panic message printed on the alternate screen in raw mode is gone as soon as it's shown,
+    // so put the terminal back first
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+    let result = {
+        let _terminal = RawTerminal::enter(&mut out)?;
+        play(&mut out, seed, &hinter)
+    };
+    hinter.stop();
+    result
+}
+
+// Raw mode and the alternate screen, undone when this is dropped however play ends
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter<W: Write>(out: &mut W) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+// The bot lives on its own thread so the game keeps going while it thinks. It only ever works on
+// the newest position it's been asked about.
+struct Hinter {
+    requests: Sender<(Position, Board, Randomizer)>,
+    hints: Receiver<(Position, Option<Move>)>,
+    thread: JoinHandle<()>,
+}
+
+impl Hinter {
+    fn start(bot: String, seed: u64, think_time: Duration) -> Result<Self, ClientError> {
+        let (request_sender, requests) = mpsc::channel::<(Position, Board, Randomizer)>();
+        let (hint_sender, hints) = mpsc::channel();
+        let (ready_sender, ready) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut player = match hint_player(&bot, seed, think_time) {
+                Ok(player) => player,
+                Err(error) => {
+                    let _ = ready_sender.send(Err(error));
+                    return;
+                }
+            };
+            let _ = ready_sender.send(Ok(()));
+            while let Ok(mut request) = requests.recv() {
+                while let Ok(newer) = requests.try_recv() {
+                    request = newer;
+                }
+                let (position, board, randomizer) = request;
+                let mv = player.next_move(&board, &randomizer);
+                if hint_sender.send((position, mv)).is_err() {
+                    break;
+                }
+            }
+        });
+        ready.recv().expect("the hint thread died")?;
+        Ok(Hinter {
+            requests: request_sender,
+            hints,
+            thread,
+        })
+    }
+
+    // Waits for the bot to finish up so a tbp bot gets told to quit
+    fn stop(self) {
+        drop(self.requests);
+        let _ = self.thread.join();
+    }
+}
+
+// With the bots feature this is anything versus takes: beam, mcts, expectimax, random or a command
+// for a tbp bot. Without it only the tbp bot command is left.
+#[cfg(feature = "bots")]
+fn hint_player(bot: &str, seed: u64, think_time: Duration) -> Result<Box<dyn Player>, ClientError> {
+    versus::make_player(bot, seed, think_time).map(|(_, player)| player)
+}
+
+#[cfg(not(feature = "bots"))]
+fn hint_player(
+    bot: &str,
+    _seed: u64,
+    think_time: Duration,
+) -> Result<Box<dyn Player>, ClientError> {
+    Ok(Box::new(TbpPlayer::spawn(bot, think_time)?))
+}
+
+fn play<W: Write>(out: &mut W, mut seed: u64, hinter: &Hinter) -> io::Result<()> {
+    let mut game = Tetris::new(seed);
+    let mut show_hint = false;
+    // The bot's move for the position, worked out once per piece
+    let mut hint: Option<(Position, Option<Move>)> = None;
+    let mut asked: Option<Position> = None;
+
+    loop {
+        let position = (seed, game.pieces, game.board.can_hold);
+        if show_hint && asked != Some(position) && game.over.is_none() {
+            let request = (position, game.board.clone(), game.randomizer.clone());
+            if hinter.requests.send(request).is_err() {
+                return Err(io::Error::other("the bot giving hints stopped"));
+            }
+            asked = Some(position);
+        }
+        match hinter.hints.try_recv() {
+            Ok(new_hint) => hint = Some(new_hint),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                return Err(io::Error::other("the bot giving hints stopped"))
+            }
+        }
+        let hint_move = hint.and_then(|(at, mv)| mv.filter(|_| show_hint && at == position));
+        draw(out, &game, hint_move)?;
+
+        if event::poll(Duration::from_millis(16))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Left => game.shift(-1),
+                    KeyCode::Right => game.shift(1),
+                    KeyCode::Down => game.fall(),
+                    KeyCode::Char(' ') => game.hard_drop(),
+                    KeyCode::Up | KeyCode::Char('x') => game.rotate(Rotation::Clockwise),
+                    KeyCode::Char('z') => game.rotate(Rotation::AntiClockwise),
+                    KeyCode::Char('c') => game.hold(),
+                    KeyCode::Char('h') => show_hint = !show_hint,
+                    KeyCode::Char('r') => {
+                        seed += 1;
+                        game = Tetris::new(seed);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        game.tick();
+    }
+    Ok(())
+}
+
+fn draw<W: Write>(out: &mut W, game: &Tetris, hint: Option<Move>) -> io::Result<()> {
+    let ghost = hint.unwrap_or_else(|| game.landing());
+    let mut frame = Vec::new();
+    game.shown_board().render(&mut frame, Some(&ghost))?;
+
+    let mut status = format!(
+        "pieces {}  lines {}  attack {}",
+        game.pieces, game.lines, game.attack
+    );
+    if !game.last_clear.is_empty() {
+        status.push_str(&format!("  last: {}", game.last_clear));
+    }
+    let mut lines = vec![String::new(), status];
+    if let Some(mv) = hint {
+        if mv.location.piece != game.falling.location.piece {
+            lines.push(format!(
+                "hint: hold, then place the {:?}",
+                mv.location.piece
+            ));
+        } else {
+            lines.push("hint shown as the ghost".to_string());
+        }
+    }
+    if let Some(end) = game.over {
+        lines.push(format!("Game over ({:?}), r to play again", end));
+    }
+    lines.push(HELP.to_string());
+
+    // Raw mode needs \r\n, and every line gets cleared to the end in case the last frame was wider
+    queue!(out, cursor::MoveTo(0, 0))?;
+    let frame = String::from_utf8_lossy(&frame);
+    for line in frame.lines().chain(lines.iter().map(|line| line.as_str())) {
+        queue!(out, Print(line))?;
+        queue!(out, terminal::Clear(ClearType::UntilNewLine))?;
+        queue!(out, Print("\r\n"))?;
+    }
+    queue!(out, terminal::Clear(ClearType::FromCursorDown))?;
+    out.flush()
+}
@@ -0,0 +1,89 @@
+use bin_utils::parse_arg;
+use lib::TbpClient;
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+mod scenarios;
+
+use crate::scenarios::*;
+
+// Usage: test-tetris-bot <bot command> [--timeout 5000] [--think 50] [--turns 50] [--seed 0]
+// Runs a tbp bot through everything a frontend might do to it and says what it got wrong. Every
+// scenario gets a new copy of the bot.
+fn main() {
+    let mut command = None;
+    let mut timeout = Duration::from_secs(5);
+    let mut think_time = Duration::from_millis(50);
+    let mut turns = 50;
+    let mut seed = 0;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => timeout = Duration::from_millis(parse_arg(args.next(), "timeout")),
+            "--think" => think_time = Duration::from_millis(parse_arg(args.next(), "think time")),
+            "--turns" => turns = parse_arg(args.next(), "turns"),
+            "--seed" => seed = parse_arg(args.next(), "seed"),
+            _ => command = Some(arg),
+        }
+    }
+    let Some(command) = command.filter(|command| !command.trim().is_empty()) else {
+        eprintln!(
+            "Usage: test-tetris-bot <bot command> [--timeout 5000] [--think 50] [--turns 50]"
+        );
+        eprintln!("                       [--seed 0]");
+        return;
+    };
+
+    let mut tester = Tester {
+        bot: Box::new(move |timeout| {
+            let mut parts = command.split_whitespace();
+            let program = parts.next().unwrap();
+            TbpClient::spawn(program, parts, timeout)
+        }),
+        timeout,
+        think_time,
+        turns,
+        seed,
+        handshakes: Vec::new(),
+        suggestions: Vec::new(),
+    };
+
+    let mut failed = 0;
+    for (name, scenario) in SCENARIOS {
+        let start = Instant::now();
+        match scenario(&mut tester) {
+            Ok(()) => println!("  ok    {} ({:.1?})", name, start.elapsed()),
+            Err(reason) => {
+                println!("  FAIL  {}: {}", name, reason);
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("handshake: {}", timings(&tester.handshakes));
+    println!("suggest:   {}", timings(&tester.suggestions));
+    println!(
+        "{} of {} scenarios passed",
+        SCENARIOS.len() - failed,
+        SCENARIOS.len()
+    );
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn timings(times: &[Duration]) -> String {
+    if times.is_empty() {
+        return "never happened".to_string();
+    }
+    let total: Duration = times.iter().sum();
+    format!(
+        "average {:.1?}, slowest {:.1?} over {}",
+        total / times.len() as u32,
+        times.iter().max().unwrap(),
+        times.len()
+    )
+}
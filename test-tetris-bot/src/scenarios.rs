@@ -0,0 +1,429 @@
+use lib::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Tester {
+    // Starts a new copy of the bot and goes through the handshake, given how long each reply gets
+    pub bot: Box<dyn Fn(Duration) -> Result<TbpClient, ClientError>>,
+    // How long the bot gets to answer anything
+    pub timeout: Duration,
+    // How long the bot gets to think before every suggest
+    pub think_time: Duration,
+    pub turns: usize,
+    pub seed: u64,
+    pub handshakes: Vec<Duration>,
+    pub suggestions: Vec<Duration>,
+}
+
+// A scenario stops at the first thing the bot gets wrong and says what it was
+pub type Scenario = fn(&mut Tester) -> Result<(), String>;
+
+pub const SCENARIOS: [(&str, Scenario); 9] = [
+    ("handshake", handshake),
+    ("suggest before start", suggest_before_start),
+    ("suggest after start", suggest_after_start),
+    ("suggest twice", suggest_twice),
+    ("play a game", play_game),
+    ("new pieces before suggest", new_pieces_before_suggest),
+    ("stop and restart", stop_and_restart),
+    ("malformed input", malformed_input),
+    ("quit", quit),
+];
+
+// Pieces come from a seeded bag so a failure can be run again
+struct Pieces {
+    randomizer: Randomizer,
+    rng: StdRng,
+}
+
+impl Pieces {
+    fn new(seed: u64) -> Self {
+        Pieces {
+            randomizer: Randomizer::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn next(&mut self) -> Piece {
+        self.randomizer.next_piece(&mut self.rng)
+    }
+
+    fn board(&mut self, queue: usize) -> Board {
+        Board::new((0..queue).map(|_| self.next()).collect())
+    }
+}
+
+impl Tester {
+    fn launch(&mut self) -> Result<TbpClient, String> {
+        let start = Instant::now();
+        let client = (self.bot)(self.timeout).map_err(|e| format!("handshake failed: {}", e))?;
+        self.handshakes.push(start.elapsed());
+        Ok(client)
+    }
+
+    // Let the bot think, ask for its moves and check every one of them could really be played
+    fn suggest(&mut self, client: &mut TbpClient, board: &Board) -> Result<Move, String> {
+        thread::sleep(self.think_time);
+        let start = Instant::now();
+        let moves = client
+            .suggest(self.timeout)
+            .map_err(|e| format!("suggest failed: {}", e))?;
+        self.suggestions.push(start.elapsed());
+        check_moves(board, &moves)?;
+        Ok(moves[0])
+    }
+}
+
+fn check_moves(board: &Board, moves: &[Move]) -> Result<(), String> {
+    if moves.is_empty() {
+        return Err("the suggestion didn't have any moves".to_string());
+    }
+    let legal = board.gen_moves();
+    for mv in moves {
+        if legal.contains(mv) {
+            continue;
+        }
+        return Err(match legal.iter().find(|l| l.location == mv.location) {
+            Some(right) => format!("suggested {:?} with spin {:?}", right.location, mv.spin),
+            None => format!("suggested {:?} which can't be played", mv.location),
+        });
+    }
+    Ok(())
+}
+
+// Not against the rules, but there's nothing left to test once it happens
+fn topped_out(board: &Board) -> bool {
+    // Holding with an empty hold can use up the whole queue
+    let piece = board.next_pieces().first();
+    piece.is_some_and(|&piece| board.collision(&Location::spawn(piece)))
+}
+
+fn sent<T>(result: Result<T, ClientError>) -> Result<T, String> {
+    result.map_err(|e| format!("couldn't send to the bot: {}", e))
+}
+
+fn handshake(tester: &mut Tester) -> Result<(), String> {
+    let client = tester.launch()?;
+    if client.info.name.is_empty() {
+        return Err("the info message has an empty name".to_string());
+    }
+    Ok(())
+}
+
+// There's nothing to suggest for yet, so either an error or a suggestion is fine as long as there's
+// an answer and the bot still works afterwards
+fn suggest_before_start(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    sent(client.send(&FrontendMessage::Suggest))?;
+    match client.recv_timeout(tester.timeout) {
+        Ok(BotReply::Suggestion { .. }) | Err(ClientError::Bot(_)) => {}
+        Ok(reply) => return Err(format!("unexpected message {:?}", reply)),
+        Err(e) => return Err(format!("suggest before start: {}", e)),
+    }
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    tester
+        .suggest(&mut client, &board)
+        .map_err(|e| format!("after the start: {}", e))?;
+    Ok(())
+}
+
+fn suggest_after_start(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    tester.suggest(&mut client, &board)?;
+    Ok(())
+}
+
+fn suggest_twice(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    tester.suggest(&mut client, &board)?;
+    tester
+        .suggest(&mut client, &board)
+        .map_err(|e| format!("second suggest: {}", e))?;
+    Ok(())
+}
+
+// The usual loop for a frontend: suggest, play the best move, then show the next piece
+fn play_game(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let mut board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    for turn in 1..=tester.turns {
+        let mv = tester
+            .suggest(&mut client, &board)
+            .map_err(|e| format!("turn {}: {}", turn, e))?;
+        sent(client.play(mv))?;
+        board.make_move_in_place(mv);
+        let piece = pieces.next();
+        board.queue.push(piece);
+        sent(client.new_piece(piece))?;
+        if topped_out(&board) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Starts with only the current piece, so nothing can be played without the new ones
+fn new_pieces_before_suggest(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let mut board = pieces.board(1);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    for turn in 1..=tester.turns {
+        let piece = pieces.next();
+        board.queue.push(piece);
+        sent(client.new_piece(piece))?;
+        let mv = tester
+            .suggest(&mut client, &board)
+            .map_err(|e| format!("turn {}: {}", turn, e))?;
+        sent(client.play(mv))?;
+        board.make_move_in_place(mv);
+        if topped_out(&board) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Every start after a stop has a different board, with garbage, a hold piece and a combo going,
+// so a bot still thinking about the old one gets caught
+fn stop_and_restart(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    tester.suggest(&mut client, &board)?;
+    for restart in 1..=3 {
+        sent(client.stop())?;
+        let hold = pieces.next();
+        let mut board = pieces.board(6);
+        board.hold = Some(hold);
+        board.combo = restart;
+        board.back_to_back = restart % 2 == 1;
+        for line in 0..restart as usize * 2 {
+            board.insert_garbage((line * 3) % 10);
+        }
+        sent(client.start(&board, Some(&pieces.randomizer)))?;
+        tester
+            .suggest(&mut client, &board)
+            .map_err(|e| format!("restart {}: {}", restart, e))?;
+    }
+    Ok(())
+}
+
+// Bots should survive things they don't understand. Replying with an error is fine.
+fn malformed_input(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    for line in [
+        "this isn't json",
+        r#"{"type":"a_message_from_the_future","stuff":[1,2,3]}"#,
+        r#"{"type":"play"}"#,
+    ] {
+        sent(client.send_line(line))?;
+    }
+    thread::sleep(tester.think_time);
+    sent(client.send(&FrontendMessage::Suggest))?;
+    let start = Instant::now();
+    loop {
+        match client.recv_timeout(tester.timeout) {
+            Ok(BotReply::Suggestion { moves, .. }) => {
+                tester.suggestions.push(start.elapsed());
+                return check_moves(&board, &moves);
+            }
+            Err(ClientError::Bot(_)) => continue,
+            Ok(reply) => return Err(format!("unexpected message {:?}", reply)),
+            Err(e) => return Err(format!("suggest after the bad messages failed: {}", e)),
+        }
+    }
+}
+
+fn quit(tester: &mut Tester) -> Result<(), String> {
+    let mut client = tester.launch()?;
+    let mut pieces = Pieces::new(tester.seed);
+    let board = pieces.board(6);
+    sent(client.start(&board, Some(&pieces.randomizer)))?;
+    tester.suggest(&mut client, &board)?;
+
+    // The client only waits for the timeout before it kills the bot
+    match client.quit() {
+        Ok(()) => Ok(()),
+        Err(ClientError::Timeout) => Err("the bot was still running after quit".to_string()),
+        Err(e) => Err(format!("couldn't wait for the bot to exit: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, PipeReader, PipeWriter, Write};
+
+    // Ways a bot can get tbp wrong, one for every scenario to catch
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Flaw {
+        NoName,
+        SilentBeforeStart,
+        IllegalMoves,
+        AnswersOnce,
+        IgnoresPlays,
+        IgnoresNewPieces,
+        KeepsOldBoard,
+        DiesOnBadInput,
+        IgnoresQuit,
+    }
+
+    // Always suggests the first move gen_moves has, and gets `flaw` wrong if there is one
+    fn fake_bot(flaw: Option<Flaw>, input: PipeReader, mut output: PipeWriter) {
+        let mut reply = |reply: BotReply| {
+            // Nobody's listening once the client's gone, which is fine
+            let _ = writeln!(output, "{}", serde_json::to_string(&reply).unwrap());
+        };
+        let name = if flaw == Some(Flaw::NoName) {
+            ""
+        } else {
+            "Fake"
+        };
+        reply(BotReply::Info(BotDetails {
+            name: name.to_string(),
+            version: "1".to_string(),
+            author: "tests".to_string(),
+            features: Vec::new(),
+        }));
+
+        let mut board: Option<Board> = None;
+        let mut suggested = false;
+        for line in BufReader::new(input).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            let message = match parse_frontend_message(&line) {
+                Ok(message) => message,
+                Err(_) if flaw == Some(Flaw::DiesOnBadInput) => return,
+                Err(e) => {
+                    reply(BotReply::Error {
+                        reason: format!("{:?}", e),
+                    });
+                    continue;
+                }
+            };
+            match message {
+                FrontendMessage::Rules(_) => reply(BotReply::Ready),
+                FrontendMessage::Start(tbp_board) => {
+                    if flaw != Some(Flaw::KeepsOldBoard) || board.is_none() {
+                        board = Some(Board::from_tbp(tbp_board));
+                    }
+                    suggested = false;
+                }
+                FrontendMessage::Stop => {
+                    if flaw != Some(Flaw::KeepsOldBoard) {
+                        board = None;
+                    }
+                }
+                FrontendMessage::Suggest => {
+                    let Some(board) = &board else {
+                        if flaw != Some(Flaw::SilentBeforeStart) {
+                            reply(BotReply::Error {
+                                reason: "not started".to_string(),
+                            });
+                        }
+                        continue;
+                    };
+                    if suggested && flaw == Some(Flaw::AnswersOnce) {
+                        continue;
+                    }
+                    suggested = true;
+                    let Some(&mv) = board.gen_moves().first() else {
+                        reply(BotReply::Error {
+                            reason: "nothing to play".to_string(),
+                        });
+                        continue;
+                    };
+                    let mut mv = mv;
+                    if flaw == Some(Flaw::IllegalMoves) {
+                        // Floating in the air
+                        mv.location.y += 1;
+                    }
+                    reply(BotReply::Suggestion {
+                        moves: vec![mv],
+                        move_info: None,
+                    });
+                }
+                FrontendMessage::Play { mv } => {
+                    if let Some(board) = &mut board {
+                        if flaw != Some(Flaw::IgnoresPlays) {
+                            board.make_move_in_place(mv);
+                        }
+                    }
+                }
+                FrontendMessage::NewPiece { piece } => {
+                    if let Some(board) = &mut board {
+                        if flaw != Some(Flaw::IgnoresNewPieces) {
+                            board.queue.push(piece);
+                        }
+                    }
+                }
+                FrontendMessage::Quit => {
+                    if flaw == Some(Flaw::IgnoresQuit) {
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn tester(flaw: Option<Flaw>) -> Tester {
+        Tester {
+            bot: Box::new(move |timeout| {
+                TbpClient::in_process(move |input, output| fake_bot(flaw, input, output), timeout)
+            }),
+            timeout: Duration::from_millis(300),
+            think_time: Duration::ZERO,
+            turns: 5,
+            seed: 1,
+            handshakes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn good_bot_passes() {
+        let mut tester = tester(None);
+        for (name, scenario) in SCENARIOS {
+            assert_eq!(Ok(()), scenario(&mut tester), "{}", name);
+        }
+        assert_eq!(SCENARIOS.len(), tester.handshakes.len());
+    }
+
+    #[test]
+    fn every_scenario_catches_something() {
+        let flaws = [
+            Flaw::NoName,
+            Flaw::SilentBeforeStart,
+            Flaw::IllegalMoves,
+            Flaw::AnswersOnce,
+            Flaw::IgnoresPlays,
+            Flaw::IgnoresNewPieces,
+            Flaw::KeepsOldBoard,
+            Flaw::DiesOnBadInput,
+            Flaw::IgnoresQuit,
+        ];
+        for ((name, scenario), flaw) in SCENARIOS.into_iter().zip(flaws) {
+            let result = scenario(&mut tester(Some(flaw)));
+            assert!(result.is_err(), "{} passed a bot with {:?}", name, flaw);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use bin_utils::parse_arg;
+use lib::*;
+use std::env;
+use std::time::{Duration, Instant};
+use versus::*;
+
+// Usage: tournament <bot> <bot> [bot...] [--gauntlet] [--games 10] [--seed 0] [--max-pieces 1000]
+//        [--think 100]
+// Round robin plays every pair of bots, the gauntlet plays the first bot against each of the
+// others. Each game is played twice on the same seed with the bots swapping sides, which cancels
+// out the luck of the pieces.
+fn main() {
+    let mut bots = Vec::new();
+    let mut gauntlet = false;
+    let mut games = 10;
+    let mut seed = 0;
+    let mut config = GameConfig::default();
+    let mut think_time = Duration::from_millis(100);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gauntlet" => gauntlet = true,
+            "--games" => games = parse_arg(args.next(), "games"),
+            "--seed" => seed = parse_arg(args.next(), "seed"),
+            "--max-pieces" => config.max_pieces = parse_arg(args.next(), "max pieces"),
+            "--think" => think_time = Duration::from_millis(parse_arg(args.next(), "think time")),
+            _ => bots.push(arg),
+        }
+    }
+    if bots.len() < 2 {
+        eprintln!("Usage: tournament <bot> <bot> [bot...] [--gauntlet] [--games 10] [--seed 0]");
+        eprintln!("                  [--max-pieces 1000] [--think 100]");
+        return;
+    }
+
+    let mut names = Vec::new();
+    let mut players = Vec::new();
+    for (i, bot) in bots.iter().enumerate() {
+        let (name, player) =
+            make_player(bot, seed + i as u64, think_time).expect("couldn't start the bot");
+        names.push(name);
+        players.push(player);
+    }
+
+    let pairings: Vec<(usize, usize)> = if gauntlet {
+        (1..players.len()).map(|b| (0, b)).collect()
+    } else {
+        (0..players.len())
+            .flat_map(|a| (a + 1..players.len()).map(move |b| (a, b)))
+            .collect()
+    };
+
+    let start = Instant::now();
+    let mut outcomes = Vec::new();
+    let mut efforts = vec![Effort::default(); players.len()];
+    for &(a, b) in &pairings {
+        let mut total = 0.0;
+        for game in 0..games {
+            let game_seed = seed + game;
+            let (first, second) = pair_mut(&mut players, a, b);
+            let a_first = play_match([first, second], &config, game_seed);
+            let (first, second) = pair_mut(&mut players, a, b);
+            let b_first = play_match([second, first], &config, game_seed);
+            for (player, effort) in [(a, a_first.effort[0]), (b, a_first.effort[1])] {
+                efforts[player].merge(&effort);
+            }
+            for (player, effort) in [(b, b_first.effort[0]), (a, b_first.effort[1])] {
+                efforts[player].merge(&effort);
+            }
+            let score = (score(&a_first, 0) + score(&b_first, 1)) / 2.0;
+            total += score;
+            outcomes.push(Outcome { a, b, score });
+        }
+        println!(
+            "{} vs {}: {:.1} - {:.1}",
+            names[a],
+            names[b],
+            total * 2.0,
+            (games as f64 - total) * 2.0
+        );
+    }
+
+    let ratings = ratings(players.len(), &outcomes, 1000, seed);
+    let mut standings: Vec<usize> = (0..players.len()).collect();
+    standings.sort_by(|&x, &y| ratings[y].elo.total_cmp(&ratings[x].elo));
+
+    println!();
+    println!(
+        "Played {} games in {:.1?}",
+        outcomes.len() * 2,
+        start.elapsed()
+    );
+    for (place, &player) in standings.iter().enumerate() {
+        let rating = ratings[player];
+        let (score, played) = outcomes
+            .iter()
+            .filter_map(|outcome| {
+                if outcome.a == player {
+                    Some(outcome.score)
+                } else if outcome.b == player {
+                    Some(1.0 - outcome.score)
+                } else {
+                    None
+                }
+            })
+            .fold((0.0, 0), |(score, played), s| (score + s, played + 1));
+        println!(
+            "{:>2}. {:<30} {:>6.0} ({:.0} to {:.0})  {:.1}% of {} games",
+            place + 1,
+            names[player],
+            rating.elo,
+            rating.low,
+            rating.high,
+            100.0 * score / played.max(1) as f64,
+            played * 2
+        );
+    }
+
+    // Only bots that say something about their search show up here
+    if efforts.iter().any(|effort| effort.moves > 0) {
+        println!();
+        for &player in &standings {
+            if efforts[player].moves > 0 {
+                println!("{:<34} {}", names[player], efforts[player]);
+            }
+        }
+    }
+}
+
+// How well `side` did in a match, 1 for a win and 0.5 for a draw
+fn score(result: &MatchResult, side: usize) -> f64 {
+    match result.winner {
+        Some(winner) if winner == side => 1.0,
+        Some(_) => 0.0,
+        None => 0.5,
+    }
+}
+
+// Borrow two different players at once
+fn pair_mut(
+    players: &mut [Box<dyn Player>],
+    a: usize,
+    b: usize,
+) -> (&mut dyn Player, &mut dyn Player) {
+    let (low, high) = players.split_at_mut(b);
+    (low[a].as_mut(), high[0].as_mut())
+}
@@ -0,0 +1,238 @@
+//! Two players on the same piece sequence sending garbage at each other, along with the players
+//! the versus binary knows how to make.
+
+use beam_search_bot::{BeamBot, BeamSearch};
+use expectimax_bot::ExpectimaxBot;
+use lib::*;
+use mcts_bot::MctsBot;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::time::Duration;
+
+mod ratings;
+
+pub use crate::ratings::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchResult {
+    // None when both topped out on the same turn or the piece limit was hit
+    pub winner: Option<usize>,
+    pub results: [GameResult; 2],
+    pub effort: [Effort; 2],
+}
+
+// How much searching went into a player's moves, added up from the MoveInfo it gave with each
+// one. Anything a player didn't say counts as 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Effort {
+    // Moves that came with info, the rest are averages over these
+    pub moves: u32,
+    pub nodes: u64,
+    pub nps: f64,
+    pub depth: u64,
+}
+
+impl Effort {
+    pub fn add(&mut self, info: &MoveInfo) {
+        self.moves += 1;
+        self.nodes += info.nodes.unwrap_or(0);
+        self.nps += info.nps.unwrap_or(0.0);
+        self.depth += info.depth.unwrap_or(0) as u64;
+    }
+
+    pub fn merge(&mut self, other: &Effort) {
+        self.moves += other.moves;
+        self.nodes += other.nodes;
+        self.nps += other.nps;
+        self.depth += other.depth;
+    }
+}
+
+impl fmt::Display for Effort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.moves == 0 {
+            return write!(f, "no search info");
+        }
+        let moves = self.moves as f64;
+        let mut parts = Vec::new();
+        if self.nodes > 0 {
+            parts.push(format!("{:.0} nodes per move", self.nodes as f64 / moves));
+        }
+        if self.nps > 0.0 {
+            parts.push(format!("{:.0} nps", self.nps / moves));
+        }
+        if self.depth > 0 {
+            parts.push(format!("depth {:.1}", self.depth as f64 / moves));
+        }
+        if parts.is_empty() {
+            return write!(f, "nothing useful in the search info");
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// Both games get the same seed so they see the same pieces. The players take turns placing one
+// piece each and whatever one of them sends goes into the other's garbage queue.
+pub fn play_match(
+    mut players: [&mut dyn Player; 2],
+    config: &GameConfig,
+    seed: u64,
+) -> MatchResult {
+    let mut games = [
+        Game::new(config.clone(), seed),
+        Game::new(config.clone(), seed),
+    ];
+    let mut effort = [Effort::default(); 2];
+    while !games[0].is_over() && !games[1].is_over() {
+        let mut sent = [0; 2];
+        for (side, player) in players.iter_mut().enumerate() {
+            if let Some(turn) = games[side].step(*player) {
+                sent[side] = turn.sent;
+                if let Some(info) = player.move_info() {
+                    effort[side].add(info);
+                }
+            }
+        }
+        games[1].add_garbage(sent[0]);
+        games[0].add_garbage(sent[1]);
+    }
+
+    let results = [games[0].result(), games[1].result()];
+    let winner = match (results[0].end.topped_out(), results[1].end.topped_out()) {
+        (false, true) => Some(0),
+        (true, false) => Some(1),
+        _ => None,
+    };
+    MatchResult {
+        winner,
+        results,
+        effort,
+    }
+}
+
+// A bot is one of random, beam, mcts or expectimax, or else a command that runs a tbp bot. Gives
+// back a name for the bot along with it. The built-in bots other than random are the same ones
+// their binaries run, talking tbp to us on a thread instead of over a pipe.
+pub fn make_player(
+    bot: &str,
+    seed: u64,
+    think_time: Duration,
+) -> Result<(String, Box<dyn Player>), ClientError> {
+    let player: Box<dyn Player> = match bot {
+        "random" => Box::new(RandomPlayer::new(seed)),
+        "beam" => Box::new(TbpPlayer::from_bot::<BeamBot>(
+            beam_search_bot::BOT_INFO,
+            BeamSearch::default(),
+            think_time,
+        )?),
+        "mcts" => Box::new(TbpPlayer::from_bot::<MctsBot>(
+            mcts_bot::BOT_INFO,
+            mcts_bot::Settings::default(),
+            think_time,
+        )?),
+        "expectimax" => Box::new(TbpPlayer::from_bot::<ExpectimaxBot>(
+            expectimax_bot::BOT_INFO,
+            expectimax_bot::Settings {
+                time_limit: think_time,
+                ..Default::default()
+            },
+            think_time,
+        )?),
+        command => {
+            let player = TbpPlayer::spawn(command, think_time)?;
+            return Ok((player.name.clone(), Box::new(player)));
+        }
+    };
+    Ok((bot.to_string(), player))
+}
+
+pub struct RandomPlayer {
+    rng: StdRng,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        RandomPlayer {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for RandomPlayer {
+    fn next_move(&mut self, board: &Board, _: &Randomizer) -> Option<Move> {
+        let moves = board.gen_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[self.rng.gen_range(0..moves.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::env;
+
+    // Keeps the newest piece it was shown each turn
+    struct Recorder<P: Player> {
+        player: P,
+        pieces: Vec<Piece>,
+    }
+
+    impl<P: Player> Player for Recorder<P> {
+        fn next_move(&mut self, board: &Board, randomizer: &Randomizer) -> Option<Move> {
+            self.pieces.push(*board.next_pieces().last().unwrap());
+            self.player.next_move(board, randomizer)
+        }
+    }
+
+    fn config() -> GameConfig {
+        GameConfig {
+            max_pieces: 60,
+            ..GameConfig::default()
+        }
+    }
+
+    // The beam search bot on a thread. With no think time it gets asked for its move as soon as
+    // it's started, and answers once the whole search is done, so games come out the same every
+    // time. That only holds if the suggest doesn't give up first, which a debug build with every
+    // test running at once can make it do at the usual timeout.
+    fn beam(width: usize, depth: usize) -> TbpPlayer {
+        env::set_var("TBP_SUGGEST_TIMEOUT", "60000");
+        let search = BeamSearch::new(width, depth, LinearEvaluator::default());
+        TbpPlayer::from_bot::<BeamBot>(beam_search_bot::BOT_INFO, search, Duration::ZERO).unwrap()
+    }
+
+    #[test]
+    fn same_pieces_for_both() {
+        let mut wide = Recorder {
+            player: beam(10, 2),
+            pieces: Vec::new(),
+        };
+        let mut narrow = Recorder {
+            player: beam(5, 1),
+            pieces: Vec::new(),
+        };
+        let result = play_match([&mut wide, &mut narrow], &config(), 5);
+        let [wide_result, narrow_result] = result.results;
+        // Garbage only comes from the other side, and taking it doesn't change what comes next
+        assert!(wide_result.garbage_received <= narrow_result.attack);
+        assert!(narrow_result.garbage_received <= wide_result.attack);
+        assert!(wide_result.garbage_received > 0 && narrow_result.garbage_received > 0);
+        let turns = wide.pieces.len().min(narrow.pieces.len());
+        assert!(turns > 20);
+        assert_eq!(wide.pieces[..turns], narrow.pieces[..turns]);
+    }
+
+    #[test]
+    fn garbage_goes_both_ways() {
+        // The same moves on the same pieces, so each side gets exactly what it sent
+        let mut first = beam(10, 2);
+        let mut second = beam(10, 2);
+        let result = play_match([&mut first, &mut second], &config(), 5);
+        assert_eq!(result.results[0], result.results[1]);
+        assert!(result.results[0].garbage_received > 0);
+        assert_eq!(None, result.winner);
+    }
+}
@@ -0,0 +1,92 @@
+use bin_utils::parse_arg;
+use lib::*;
+use std::env;
+use std::time::{Duration, Instant};
+use versus::*;
+
+// Usage: versus <bot> <bot> [--games 10] [--seed 0] [--max-pieces 1000] [--think 100]
+fn main() {
+    let mut bots = Vec::new();
+    let mut games = 10;
+    let mut seed = 0;
+    let mut config = GameConfig::default();
+    let mut think_time = Duration::from_millis(100);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => games = parse_arg(args.next(), "games"),
+            "--seed" => seed = parse_arg(args.next(), "seed"),
+            "--max-pieces" => config.max_pieces = parse_arg(args.next(), "max pieces"),
+            "--think" => think_time = Duration::from_millis(parse_arg(args.next(), "think time")),
+            _ => bots.push(arg),
+        }
+    }
+    if bots.len() != 2 {
+        eprintln!(
+            "Usage: versus <bot> <bot> [--games 10] [--seed 0] [--max-pieces 1000] [--think 100]"
+        );
+        return;
+    }
+
+    let (name_a, mut a) = make_player(&bots[0], seed, think_time).expect("couldn't start the bot");
+    let (name_b, mut b) =
+        make_player(&bots[1], seed + 1, think_time).expect("couldn't start the bot");
+    let mut wins = [0, 0];
+    let mut totals = [(0, 0); 2];
+    let mut efforts = [Effort::default(); 2];
+    let start = Instant::now();
+    for game in 0..games {
+        let result = play_match([a.as_mut(), b.as_mut()], &config, seed + game);
+        let [ra, rb] = result.results;
+        println!(
+            "Game {}: {} ({}, {} pieces, {} attack) vs {} ({}, {} pieces, {} attack)",
+            game + 1,
+            name_a,
+            status(&ra),
+            ra.pieces,
+            ra.attack,
+            name_b,
+            status(&rb),
+            rb.pieces,
+            rb.attack,
+        );
+        if let Some(winner) = result.winner {
+            wins[winner] += 1;
+        }
+        for (total, r) in totals.iter_mut().zip([ra, rb]) {
+            total.0 += r.pieces;
+            total.1 += r.attack;
+        }
+        for (effort, e) in efforts.iter_mut().zip(&result.effort) {
+            effort.merge(e);
+        }
+    }
+
+    println!();
+    println!(
+        "{} {} - {} {} ({} draws) in {:.1?}",
+        name_a,
+        wins[0],
+        wins[1],
+        name_b,
+        games as u32 - wins[0] - wins[1],
+        start.elapsed()
+    );
+    for ((name, (pieces, attack)), effort) in [name_a, name_b].iter().zip(totals).zip(efforts) {
+        println!(
+            "{}: {:.2} attack per piece, {}",
+            name,
+            attack as f32 / pieces.max(1) as f32,
+            effort
+        );
+    }
+}
+
+fn status(result: &GameResult) -> String {
+    if result.end.topped_out() {
+        format!("{:?}", result.end)
+    } else {
+        "survived".to_string()
+    }
+}
@@ -0,0 +1,144 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// One pairing's result from `a`'s point of view, 1 for a win, 0 for a loss and anything in
+// between for draws or an average over a pair of games
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    pub a: usize,
+    pub b: usize,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub elo: f64,
+    // 95% interval from resampling the outcomes
+    pub low: f64,
+    pub high: f64,
+}
+
+// Every pair of players gets this many made up draws on top of the real games. It keeps a player
+// that won everything from going off to infinity and pulls ratings from only a few games towards
+// each other, like a prior would.
+const PRIOR_DRAWS: f64 = 1.0;
+
+// Bradley-Terry strengths for the outcomes, as elo with the average player at 0
+pub fn fit_elo(players: usize, outcomes: &[Outcome]) -> Vec<f64> {
+    let mut wins = vec![0.0; players];
+    let mut games = vec![vec![0.0; players]; players];
+    for outcome in outcomes {
+        wins[outcome.a] += outcome.score;
+        wins[outcome.b] += 1.0 - outcome.score;
+        games[outcome.a][outcome.b] += 1.0;
+        games[outcome.b][outcome.a] += 1.0;
+    }
+    for (i, row) in games.iter_mut().enumerate() {
+        wins[i] += PRIOR_DRAWS / 2.0 * (players - 1) as f64;
+        for (j, played) in row.iter_mut().enumerate() {
+            if i != j {
+                *played += PRIOR_DRAWS;
+            }
+        }
+    }
+
+    // Minorization-maximization, see Hunter 2004
+    let mut strength = vec![1.0; players];
+    for _ in 0..1000 {
+        let mut next: Vec<f64> = (0..players)
+            .map(|i| {
+                let denominator: f64 = (0..players)
+                    .filter(|&j| j != i)
+                    .map(|j| games[i][j] / (strength[i] + strength[j]))
+                    .sum();
+                if denominator > 0.0 {
+                    wins[i] / denominator
+                } else {
+                    strength[i]
+                }
+            })
+            .collect();
+        let mean_log = next.iter().map(|s: &f64| s.ln()).sum::<f64>() / players as f64;
+        for s in &mut next {
+            *s /= mean_log.exp();
+        }
+        let change = next
+            .iter()
+            .zip(&strength)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        strength = next;
+        if change < 1e-9 {
+            break;
+        }
+    }
+    strength.iter().map(|s| 400.0 * s.log10()).collect()
+}
+
+// Elo for every player with a bootstrap confidence interval
+pub fn ratings(players: usize, outcomes: &[Outcome], resamples: usize, seed: u64) -> Vec<Rating> {
+    let elo = fit_elo(players, outcomes);
+    if outcomes.is_empty() || resamples == 0 {
+        return elo
+            .into_iter()
+            .map(|elo| Rating {
+                elo,
+                low: elo,
+                high: elo,
+            })
+            .collect();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = vec![Vec::with_capacity(resamples); players];
+    for _ in 0..resamples {
+        let resampled: Vec<Outcome> = (0..outcomes.len())
+            .map(|_| outcomes[rng.gen_range(0..outcomes.len())])
+            .collect();
+        for (player, elo) in fit_elo(players, &resampled).into_iter().enumerate() {
+            samples[player].push(elo);
+        }
+    }
+
+    elo.into_iter()
+        .zip(samples)
+        .map(|(elo, mut samples)| {
+            samples.sort_by(|a, b| a.total_cmp(b));
+            let at = |fraction: f64| samples[((samples.len() - 1) as f64 * fraction) as usize];
+            Rating {
+                elo,
+                low: at(0.025),
+                high: at(0.975),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn fit_ratings() {
+        // 0 beats 1 three games out of four and 1 beats 2 the same
+        let mut outcomes = Vec::new();
+        for (a, b) in [(0, 1), (1, 2)] {
+            for score in [1.0, 1.0, 1.0, 0.0] {
+                outcomes.push(Outcome { a, b, score });
+            }
+        }
+        let elo = fit_elo(3, &outcomes);
+        assert!(elo[0] > elo[1] && elo[1] > elo[2]);
+        assert!(elo.iter().sum::<f64>().abs() < 1e-6);
+        assert!((elo[0] - elo[1] - (elo[1] - elo[2])).abs() < 1.0);
+
+        let ratings = ratings(3, &outcomes, 200, 0);
+        for (rating, elo) in ratings.iter().zip(elo) {
+            assert_eq!(elo, rating.elo);
+            assert!(rating.low <= elo && elo <= rating.high);
+        }
+
+        // Nobody has played yet so nobody is better
+        assert_eq!(vec![0.0, 0.0], fit_elo(2, &[]));
+    }
+}